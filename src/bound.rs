@@ -0,0 +1,249 @@
+//! Proven upper bounds for the number of desks that fit into a room
+//!
+//! Every bound only looks at the floor and the spacing rule, so it also holds under the walkway
+//! and chair rules. If the solvers place fewer desks than a room requires but the bound allows
+//! more, they are too weak; if the bound is below the required count, no solver can succeed.
+
+use std::collections::{HashMap, HashSet};
+use std::fmt::{Display, Formatter};
+
+use itertools::Itertools;
+use serde::Serialize;
+
+use crate::budget::Meter;
+use crate::exact;
+use crate::layout::{Desk, Layout};
+use crate::room::{DeskShape, Floor, Metric, Rules, Spacing};
+
+/// Rooms with up to this many cells are small enough to bound by the linear relaxation
+pub const RELAXATION_CELL_LIMIT: usize = 1500;
+
+/// How many steps the relaxation takes towards the optimum of its dual
+const RELAXATION_STEPS: usize = 300;
+
+/// The most desks that can fit into a room and the argument that proves it
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Serialize)]
+pub struct Bound {
+    pub desks: usize,
+    pub method: &'static str,
+}
+
+impl Display for Bound {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} ({} bound)", self.desks, self.method)
+    }
+}
+
+/// The smallest of the bounds for desks of the given shape on a floor
+///
+/// The exhaustive searches of the [blocks] bound spend the budget of `meter`, the bound is left
+/// out if it runs out.
+pub fn maximum(floor: &Floor, shape: DeskShape, spacing: Spacing, meter: &Meter) -> Bound {
+    let open = floor.width * floor.height - floor.blocked_count();
+    let mut best = Bound { desks: counting(open, floor.width, floor.height, shape, spacing), method: "counting" };
+
+    let bounds = [("blocks", blocks(floor, shape, spacing, meter)), ("relaxation", relaxation(floor, shape, spacing))];
+    for (method, desks) in bounds {
+        if let Some(desks) = desks.filter(|desks| *desks < best.desks) {
+            best = Bound { desks, method };
+        }
+    }
+
+    best
+}
+
+/// Counts cells that every desk needs on its own
+///
+/// Desks cover distinct open cells. Growing the desks to the right or the bottom by the gap
+/// keeps them disjoint in the room grown the same way, as does growing them in both directions
+/// by the gap that the spacing implies for the chebyshev distance.
+pub fn counting(open: usize, width: usize, height: usize, shape: DeskShape, spacing: Spacing) -> usize {
+    let (length, thickness) = (shape.length.max(shape.thickness), shape.length.min(shape.thickness));
+    let Spacing { metric, gap } = spacing;
+    let diagonal_gap = match metric {
+        Metric::Chebyshev => gap,
+        Metric::Manhattan => gap / 2,
+    };
+
+    let area = length * thickness;
+    let grown = area + gap * thickness;
+
+    [
+        open / area,
+        (width + gap) * height / grown,
+        width * (height + gap) / grown,
+        (width + diagonal_gap) * (height + diagonal_gap) / ((length + diagonal_gap) * (thickness + diagonal_gap)),
+    ]
+    .into_iter()
+    .min()
+    .unwrap_or_default()
+}
+
+/// Averages the desks over every block of `size` x `size` cells that overlaps the room
+///
+/// Each desk lies within the same number of blocks, so the most desks that fit into the part of
+/// each block inside the room, summed up and divided by that number, bound the desks in the
+/// room. The blocks are solved exhaustively, which makes them small. A block the budget stops
+/// short of could hold more desks than found, so there is no bound if the budget runs out.
+pub fn blocks(floor: &Floor, shape: DeskShape, spacing: Spacing, meter: &Meter) -> Option<usize> {
+    let rules = Rules { spacing, ..Rules::default() };
+    let (length, thickness) = (shape.length.max(shape.thickness), shape.length.min(shape.thickness));
+    let mut solved = HashMap::new();
+
+    let bound = (length..)
+        .take_while(|size| size * size <= exact::CELL_LIMIT)
+        .map(|size| {
+            let mut sum = 0;
+            for top in 0..floor.height + size - 1 {
+                for left in 0..floor.width + size - 1 {
+                    let (x, y) = (left.saturating_sub(size - 1), top.saturating_sub(size - 1));
+                    let block = floor.window(x, y, (left + 1).min(floor.width) - x, (top + 1).min(floor.height) - y);
+                    sum += *solved.entry(block).or_insert_with_key(|block| {
+                        exact::maximise(&Layout::with_floor(block).under(rules), shape, meter).len()
+                    });
+                }
+            }
+            sum / ((size - length + 1) * (size - thickness + 1))
+        })
+        .min();
+
+    bound.filter(|_| !meter.is_exhausted())
+}
+
+/// Bounds the linear relaxation of the placement model by a solution of its dual
+///
+/// The model has a variable for every desk position and a constraint for every rectangle of
+/// cells that lie within the spacing gap of each other, so that at most one desk may cover its
+/// cells. The dual assigns a weight to every constraint and every position, such that the
+/// weights of each position and its constraints add up to at least one. Any such weights bound
+/// the relaxation and thus the desks. The ones on the constraints are improved by subgradient
+/// steps and those on the positions make up for what is missing.
+pub fn relaxation(floor: &Floor, shape: DeskShape, spacing: Spacing) -> Option<usize> {
+    if floor.width * floor.height > RELAXATION_CELL_LIMIT {
+        return None;
+    }
+
+    let room = Layout::with_floor(&floor.window(0, 0, floor.width, floor.height))
+        .under(Rules { spacing, ..Rules::default() });
+    let desks = room.candidates(shape);
+    let constraints = cliques(&room, &desks);
+
+    let mut memberships = vec![0; desks.len()];
+    constraints.iter().flatten().for_each(|desk| memberships[*desk] += 1);
+    let most = memberships.into_iter().max().unwrap_or_default().max(1);
+    let mut weights = vec![1.0 / most as f64; constraints.len()];
+    let mut best = desks.len() as f64;
+
+    for step in 0..RELAXATION_STEPS {
+        let mut covered = vec![0.0; desks.len()];
+        for (constraint, weight) in constraints.iter().zip(&weights) {
+            constraint.iter().for_each(|desk| covered[*desk] += weight);
+        }
+
+        let missing = covered.iter().map(|covered| (1.0 - covered).max(0.0)).sum::<f64>();
+        best = best.min(weights.iter().sum::<f64>() + missing);
+
+        let size = 0.5 / most as f64 / (step as f64 + 1.0).sqrt();
+        for (constraint, weight) in constraints.iter().zip(&mut weights) {
+            let short = constraint.iter().filter(|desk| covered[**desk] < 1.0).count();
+            *weight = (*weight - size * (1.0 - short as f64)).max(0.0);
+        }
+    }
+
+    // Allow for rounding errors in the sums before cutting off the fraction
+    Some((best + 1e-6).floor() as usize)
+}
+
+/// The sets of desks covering a cell of the same rectangle, for every rectangle overlapping the
+/// room whose cells are all within the spacing gap of each other
+fn cliques(room: &Layout, desks: &[Desk]) -> Vec<Vec<usize>> {
+    let Spacing { metric, gap } = room.rules().spacing;
+    let rectangles = match metric {
+        Metric::Chebyshev => vec![(gap + 1, gap + 1)],
+        Metric::Manhattan => (0..=gap).map(|width| (width + 1, gap - width + 1)).collect(),
+    };
+
+    let mut covering = vec![Vec::new(); room.width * room.height];
+    for (index, desk) in desks.iter().enumerate() {
+        desk.cells().for_each(|(x, y)| covering[y * room.width + x].push(index));
+    }
+
+    let mut cliques = HashSet::new();
+    for (width, height) in rectangles {
+        for bottom in 0..room.height + height - 1 {
+            for right in 0..room.width + width - 1 {
+                let clique = (bottom.saturating_sub(height - 1)..(bottom + 1).min(room.height))
+                    .flat_map(|y| (right.saturating_sub(width - 1)..(right + 1).min(room.width)).map(move |x| (x, y)))
+                    .flat_map(|(x, y)| covering[y * room.width + x].iter().copied())
+                    .sorted_unstable()
+                    .dedup()
+                    .collect::<Vec<_>>();
+                if clique.len() > 1 {
+                    cliques.insert(clique);
+                }
+            }
+        }
+    }
+
+    cliques.into_iter().collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{blocks, counting, maximum, relaxation};
+    use crate::budget::{Budget, Meter};
+    use crate::exact;
+    use crate::layout::Layout;
+    use crate::levels::level5::solve;
+    use crate::room::{DeskShape, Floor, FloorPlan, Metric, Rules, Spacing};
+
+    #[test]
+    pub fn test_bounds() {
+        let shape = DeskShape::new(2, 1);
+        for spacing in [Spacing::default(), Spacing::new(Metric::Manhattan, 1), Spacing::new(Metric::Chebyshev, 2)] {
+            for (width, height) in [(3, 3), (5, 4), (6, 6), (3, 12)] {
+                let room = Layout::new(width, height).under(Rules { spacing, ..Rules::default() });
+                let optimum = exact::maximise(&room, shape, &Meter::default()).len();
+                let bound = maximum(&Floor::open(width, height), shape, spacing, &Meter::default());
+                assert!(bound.desks >= optimum, "{bound} for {optimum} desks in {width}x{height} with {spacing}");
+            }
+        }
+
+        let manhattan = Spacing::new(Metric::Manhattan, 1);
+        assert_eq!(counting(36, 6, 6, shape, manhattan), 14);
+        assert_eq!(blocks(&Floor::open(6, 6), shape, manhattan, &Meter::default()), Some(11));
+        let starved = Budget { nodes: Some(10), ..Budget::default() }.start();
+        assert_eq!(blocks(&Floor::open(6, 6), shape, manhattan, &starved), None);
+        assert_eq!(maximum(&Floor::open(6, 6), shape, manhattan, &starved).method, "relaxation");
+        assert_eq!(relaxation(&Floor::open(6, 6), shape, manhattan), Some(10));
+        assert_eq!(relaxation(&Floor::open(3, 12), shape, Spacing::new(Metric::Chebyshev, 2)), Some(4));
+    }
+
+    #[test]
+    pub fn test_shortfall() {
+        let impossible = solve(&"3 3 3".parse::<FloorPlan>().unwrap(), &Meter::default()).unwrap_err();
+        assert!(impossible.to_string().starts_with("Impossible target, at most 2 (counting bound)"), "{impossible}");
+
+        let starved = Budget { nodes: Some(0), ..Budget::default() }.start();
+        let weak = solve(&"4 4 5 manhattan:1".parse::<FloorPlan>().unwrap(), &starved).unwrap_err();
+        assert!(weak.to_string().starts_with("Solver weakness"), "{weak:?}");
+
+        let solution = solve(&"4 4 4".parse::<FloorPlan>().unwrap(), &Meter::default()).unwrap();
+        assert_eq!(solution.bound.map(|bound| bound.desks), Some(4));
+    }
+
+    #[test]
+    pub fn test_thick_desks() {
+        let (turned, shape) = (DeskShape { length: 1, thickness: 2 }, DeskShape::new(2, 1));
+        for (width, height) in [(5, 1), (1, 5), (4, 4), (7, 3)] {
+            let floor = Floor::open(width, height);
+            let spacing = Spacing::default();
+            assert_eq!(counting(width * height, width, height, turned, spacing), counting(width * height, width, height, shape, spacing));
+            assert_eq!(blocks(&floor, turned, spacing, &Meter::default()), blocks(&floor, shape, spacing, &Meter::default()));
+            assert_eq!(maximum(&floor, turned, spacing, &Meter::default()), maximum(&floor, shape, spacing, &Meter::default()));
+        }
+
+        let solution = solve(&"5 1 2 1x2".parse::<FloorPlan>().unwrap(), &Meter::default()).unwrap();
+        assert_eq!(solution.bound.map(|bound| bound.desks), Some(2));
+    }
+}
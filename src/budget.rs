@@ -0,0 +1,106 @@
+//! Limits on the time and the search nodes the solvers may spend on a single subtask
+
+use std::cell::Cell;
+use std::time::{Duration, Instant};
+
+/// How much the solvers may spend on a single subtask, unlimited by default
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+pub struct Budget {
+    pub time: Option<Duration>,
+    /// The number of search nodes, i.e. branches of the exhaustive search, conflicts of the
+    /// SAT solver or steps of the local search
+    pub nodes: Option<u64>,
+}
+
+impl Budget {
+    /// Starts spending the budget
+    pub fn start(&self) -> Meter {
+        Meter {
+            deadline: self.time.map(|time| Instant::now() + time),
+            nodes: self.nodes,
+            spent: Cell::new(0),
+            exhausted: Cell::new(false),
+        }
+    }
+}
+
+/// How often [Meter::spend] looks at the clock, as reading it costs more than a search node
+const CLOCK_INTERVAL: u64 = 256;
+
+/// A started [Budget], counting the search nodes spent so far
+///
+/// Searches call [Meter::spend] for every node and return the best result found so far once
+/// it fails. A result found after that is not proven optimal. The default meter is unlimited.
+#[derive(Debug, Default)]
+pub struct Meter {
+    deadline: Option<Instant>,
+    nodes: Option<u64>,
+    spent: Cell<u64>,
+    exhausted: Cell<bool>,
+}
+
+impl Meter {
+    /// Spends one search node, returning whether the budget allowed it
+    pub fn spend(&self) -> bool {
+        if self.exhausted.get() {
+            return false;
+        }
+
+        let spent = self.spent.get();
+        let exhausted = self.nodes.is_some_and(|nodes| spent >= nodes)
+            || (spent.is_multiple_of(CLOCK_INTERVAL) && self.deadline.is_some_and(|deadline| Instant::now() >= deadline));
+        if exhausted {
+            self.exhausted.set(true);
+            return false;
+        }
+
+        self.spent.set(spent + 1);
+        true
+    }
+
+    /// Whether a search had to stop early because the budget ran out
+    pub fn is_exhausted(&self) -> bool {
+        self.exhausted.get()
+    }
+
+    pub fn spent(&self) -> u64 {
+        self.spent.get()
+    }
+
+    /// A meter with the same limits that counts its own nodes, so that work beside the search,
+    /// like bounding the room, does not use up the nodes of the search
+    pub fn sibling(&self) -> Meter {
+        Meter { deadline: self.deadline, nodes: self.nodes, spent: Cell::new(0), exhausted: Cell::new(false) }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Budget, Meter};
+    use crate::exact;
+    use crate::layout::Layout;
+    use crate::room::DeskShape;
+
+    #[test]
+    pub fn test_node_limit() {
+        let meter = Budget { nodes: Some(3), ..Budget::default() }.start();
+        assert!((0..3).all(|_| meter.spend()));
+        assert!(!meter.spend());
+        assert!(meter.is_exhausted());
+
+        let room = Layout::new(7, 7);
+        let unlimited = Meter::default();
+        let optimum = exact::maximise(&room, DeskShape::new(2, 1), &unlimited);
+        assert!(!unlimited.is_exhausted());
+
+        let meter = Budget { nodes: Some(20), ..Budget::default() }.start();
+        let layout = exact::maximise(&room, DeskShape::new(2, 1), &meter);
+        assert!(meter.is_exhausted());
+        assert!(layout.len() < optimum.len());
+        layout.check_spacing().unwrap();
+
+        let sibling = meter.sibling();
+        assert!(!sibling.is_exhausted() && sibling.spend());
+        assert_eq!((sibling.spent(), meter.spent()), (1, 20));
+    }
+}
@@ -0,0 +1,164 @@
+//! Distributing a desk demand over the rooms of a building
+//!
+//! Every room is filled with as many desks as the level5 solvers fit, which makes its capacity.
+//! The demand is then either packed into as few rooms as possible, or spread over all rooms in
+//! proportion to their capacity, and the rooms with spare capacity widen their spacing as far
+//! as their share of the desks still fits.
+
+use std::fmt::{Display, Formatter};
+
+use clap::ValueEnum;
+use eyre::eyre;
+
+use crate::bound;
+use crate::budget::Budget;
+use crate::catalog;
+use crate::layout::Layout;
+use crate::levels::level5;
+use crate::room::{FloorPlan, Spacing};
+
+/// What a good distribution achieves
+#[derive(Copy, Clone, Debug, Eq, PartialEq, ValueEnum)]
+pub enum Objective {
+    /// Use as few rooms as possible, filling the largest ones first
+    Rooms,
+    /// Fill every room to the same share of its capacity and space the desks out
+    Spacing,
+}
+
+/// The desks given to a room of the building
+#[derive(Debug)]
+pub struct Allocation {
+    pub plan: FloorPlan,
+    /// The most desks the solvers fit into the room
+    pub capacity: usize,
+    /// Whether no layout holds more than `capacity` desks
+    pub optimal: bool,
+    pub desks: usize,
+    /// The spacing the desks keep, which may be wider than the one of the room
+    pub spacing: Spacing,
+    pub layout: Layout,
+}
+
+impl Display for Allocation {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let Allocation { plan, capacity, optimal, desks, spacing, layout } = self;
+        let proven = if *optimal { "" } else { "at least " };
+        writeln!(f, "{}x{} room, {desks} of {proven}{capacity} desks, spacing {spacing}", plan.width, plan.height)?;
+        write!(f, "{}", layout.render())
+    }
+}
+
+/// Distributes `demand` desks over the rooms, spending the budget on each room
+pub fn allocate(plans: Vec<FloorPlan>, demand: usize, objective: Objective, budget: Budget) -> eyre::Result<Vec<Allocation>> {
+    let mut allocations = plans.into_iter()
+        .enumerate()
+        .map(|(index, plan)| {
+            let _span = tracing::debug_span!("room", index).entered();
+            let meter = budget.start();
+            let bound = bound::maximum(&plan.floor, plan.desk, plan.rules.spacing, &meter.sibling());
+            let placement = level5::place_desks(&FloorPlan { desk_count: bound.desks, ..plan.clone() }, &meter);
+            let (layout, optimal) = catalog::maximise(&plan, placement.layout, bound, &meter);
+            tracing::debug!(capacity = layout.len(), optimal, "Filled room");
+
+            Allocation { capacity: layout.len(), optimal, desks: 0, spacing: plan.rules.spacing, layout, plan }
+        })
+        .collect::<Vec<_>>();
+
+    let capacity = allocations.iter().map(|allocation| allocation.capacity).sum::<usize>();
+    if capacity < demand {
+        let proven = allocations.iter().all(|allocation| allocation.optimal);
+        return Err(eyre!(
+            "The rooms {} at most {capacity} of the {demand} desks",
+            if proven { "fit" } else { "were found to fit" },
+        ));
+    }
+
+    match objective {
+        Objective::Rooms => {
+            let mut order = (0..allocations.len()).collect::<Vec<_>>();
+            order.sort_by_key(|index| std::cmp::Reverse(allocations[*index].capacity));
+
+            let mut left = demand;
+            for index in order {
+                let allocation = &mut allocations[index];
+                allocation.desks = allocation.capacity.min(left);
+                left -= allocation.desks;
+            }
+        }
+        Objective::Spacing => {
+            for _ in 0..demand {
+                // The room whose share of its capacity grows the least by one more desk
+                let next = allocations.iter()
+                    .enumerate()
+                    .filter(|(_, allocation)| allocation.desks < allocation.capacity)
+                    .min_by(|(_, a), (_, b)| ((a.desks + 1) * b.capacity).cmp(&((b.desks + 1) * a.capacity)))
+                    .map(|(index, _)| index)
+                    .expect("The capacity covers the demand");
+                allocations[next].desks += 1;
+            }
+
+            for allocation in &mut allocations {
+                widen(allocation, budget);
+            }
+        }
+    }
+
+    for allocation in &mut allocations {
+        allocation.layout.truncate(allocation.desks);
+    }
+
+    Ok(allocations)
+}
+
+/// Widens the spacing of a room step by step while the level5 placement still fits its desks
+fn widen(allocation: &mut Allocation, budget: Budget) {
+    if allocation.desks == 0 || allocation.desks == allocation.capacity {
+        return;
+    }
+
+    let plan = &allocation.plan;
+    for gap in plan.rules.spacing.gap + 1..plan.width.max(plan.height) {
+        let spacing = Spacing { gap, ..plan.rules.spacing };
+        let mut wider = plan.clone();
+        wider.desk_count = allocation.desks;
+        wider.rules.spacing = spacing;
+
+        let placement = level5::place_desks(&wider, &budget.start());
+        if placement.layout.len() < allocation.desks {
+            break;
+        }
+        tracing::debug!(%spacing, desks = allocation.desks, "Widened spacing");
+        allocation.spacing = spacing;
+        allocation.layout = placement.layout;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{allocate, Objective};
+    use crate::budget::Budget;
+    use crate::input::Input;
+    use crate::levels::level5;
+
+    #[test]
+    pub fn test_allocate() {
+        let input = "3\n4 4 0\n7 7 0\n3 3 0\n".parse::<level5::Input>().unwrap();
+        let plans = || input.subtasks().map(level5::Room::plan).collect::<Vec<_>>();
+
+        let packed = allocate(plans(), 9, Objective::Rooms, Budget::default()).unwrap();
+        assert_eq!(packed.iter().map(|allocation| (allocation.capacity, allocation.desks)).collect::<Vec<_>>(), [(4, 0), (10, 9), (2, 0)]);
+        assert!(packed.iter().all(|allocation| allocation.optimal));
+
+        let spread = allocate(plans(), 9, Objective::Spacing, Budget::default()).unwrap();
+        assert_eq!(spread.iter().map(|allocation| allocation.desks).collect::<Vec<_>>(), [2, 6, 1]);
+        for allocation in &spread {
+            assert!(allocation.spacing.gap >= 2, "{allocation}");
+            assert_eq!(allocation.layout.len(), allocation.desks);
+            allocation.layout.check_spacing().unwrap();
+        }
+
+        let error = allocate(plans(), 17, Objective::Rooms, Budget::default()).unwrap_err();
+        assert_eq!(error.to_string(), "The rooms fit at most 16 of the 17 desks");
+    }
+}
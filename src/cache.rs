@@ -0,0 +1,135 @@
+//! Solutions kept on disk between runs, so that repeated and rotated rooms are solved once
+//!
+//! Solutions are stored for the canonical orientation of a subtask and transposed when a room
+//! is asked for the other way round. Every file starts with the key of its subtask, which
+//! guards against hash collisions, and lives in a directory named after the solver version.
+
+use std::fmt::Debug;
+use std::path::{Path, PathBuf};
+
+use eyre::WrapErr;
+
+/// Bump whenever a solver changes its outputs, so that the cached solutions are dropped
+pub const SOLVER_VERSION: u32 = 2;
+
+/// A subtask whose room can be solved in either orientation
+pub trait Cacheable: Debug + Sized {
+    /// The subtask with the width and height of its room swapped
+    fn transposed(&self) -> Self;
+
+    /// Whether solutions are cached for this orientation of the room
+    fn is_canonical(&self) -> bool;
+
+    /// Turns an output for [Cacheable::transposed] into an output for this subtask
+    fn transpose_output(&self, output: &str) -> eyre::Result<String>;
+}
+
+pub struct Cache {
+    dir: PathBuf,
+}
+
+impl Cache {
+    /// Opens the cache for the current solver version below `root`, removing the caches of
+    /// other versions
+    pub fn open(root: impl AsRef<Path>) -> eyre::Result<Self> {
+        let root = root.as_ref();
+        let version = format!("v{}-{SOLVER_VERSION}", env!("CARGO_PKG_VERSION"));
+        let dir = root.join(&version);
+        std::fs::create_dir_all(&dir).wrap_err(format!("Cannot create cache directory '{}'", dir.display()))?;
+
+        for entry in std::fs::read_dir(root).wrap_err(format!("Cannot read '{}'", root.display()))? {
+            let path = entry?.path();
+            if path.file_name().is_some_and(|name| name != version.as_str()) {
+                tracing::debug!("Removing stale cache '{}'", path.display());
+                std::fs::remove_dir_all(&path).wrap_err(format!("Cannot remove stale cache '{}'", path.display()))?;
+            }
+        }
+
+        Ok(Cache { dir })
+    }
+
+    fn path(&self, level: &str, key: &str) -> PathBuf {
+        self.dir.join(level).join(format!("{:016x}.out", fnv1a(key)))
+    }
+
+    /// The cached output for a subtask, if there is one that `verify` accepts
+    pub fn get<T: Cacheable>(&self, level: &str, subtask: &T, verify: impl Fn(&str) -> bool) -> Option<String> {
+        let canonical = if subtask.is_canonical() { None } else { Some(subtask.transposed()) };
+        let key = match &canonical {
+            Some(canonical) => format!("{canonical:?}"),
+            None => format!("{subtask:?}"),
+        };
+
+        let content = std::fs::read_to_string(self.path(level, &key)).ok()?;
+        let (stored, output) = content.split_once('\n')?;
+        if stored != key {
+            return None;
+        }
+
+        let output = match canonical {
+            Some(_) => subtask.transpose_output(output).ok()?,
+            None => output.to_string(),
+        };
+        if !verify(&output) {
+            tracing::warn!("Ignoring cached output that fails verification");
+            return None;
+        }
+
+        Some(output)
+    }
+
+    /// Stores the output for a subtask in the canonical orientation
+    pub fn put<T: Cacheable>(&self, level: &str, subtask: &T, output: &str) -> eyre::Result<()> {
+        let (key, output) = if subtask.is_canonical() {
+            (format!("{subtask:?}"), output.to_string())
+        } else {
+            let canonical = subtask.transposed();
+            (format!("{canonical:?}"), canonical.transpose_output(output)?)
+        };
+
+        let path = self.path(level, &key);
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir).wrap_err(format!("Cannot create cache directory '{}'", dir.display()))?;
+        }
+        std::fs::write(&path, format!("{key}\n{output}")).wrap_err(format!("Cannot write '{}'", path.display()))
+    }
+}
+
+/// A hash that stays the same across builds, unlike the one of the standard library
+fn fnv1a(key: &str) -> u64 {
+    key.bytes().fold(0xcbf2_9ce4_8422_2325, |hash, byte| (hash ^ u64::from(byte)).wrapping_mul(0x0100_0000_01b3))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Cache, Cacheable};
+    use crate::budget::Meter;
+    use crate::input::Input;
+    use crate::levels::level5;
+
+    #[test]
+    pub fn test_rotated_hits() {
+        let root = std::env::temp_dir().join(format!("ccc-cache-test-{}", std::process::id()));
+        std::fs::create_dir_all(root.join("v0.0.0-0")).unwrap();
+        let cache = Cache::open(&root).unwrap();
+        assert!(!root.join("v0.0.0-0").exists());
+
+        let input = "2\n7 4 5\n4 7 5\n".parse::<level5::Input>().unwrap();
+        let [wide, tall] = input.subtasks().collect::<Vec<_>>()[..] else { panic!() };
+        assert!(tall.is_canonical() && !wide.is_canonical());
+        fn valid(room: &level5::Room) -> impl Fn(&str) -> bool + '_ {
+            move |output| level5::verify(room, output).is_ok()
+        }
+        assert_eq!(cache.get("level5", wide, valid(wide)), None);
+
+        let solution = level5::map(wide, &Meter::default()).unwrap();
+        cache.put("level5", wide, &solution.output).unwrap();
+        assert_eq!(cache.get("level5", wide, valid(wide)), Some(solution.output));
+
+        let rotated = cache.get("level5", tall, valid(tall)).unwrap();
+        level5::verify(tall, &rotated).unwrap();
+        assert_eq!(cache.get("level5", tall, |_| false), None);
+
+        std::fs::remove_dir_all(root).unwrap();
+    }
+}
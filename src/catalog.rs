@@ -0,0 +1,376 @@
+//! Desk counts of every open room up to a size, and closed-form formulas mined from them
+//!
+//! The sweep proves the most desks that fit into each room: small rooms are searched
+//! exhaustively, larger ones start from the level5 strategies and ask the placement formula for
+//! one desk more until it is unsatisfiable or the budget runs out. Formulas are then fitted per
+//! class of rooms whose width and height leave the same remainders modulo `k`. The desks that the
+//! arithmetic of [level5::Room::generate_room_table_matrix_for_even_width] counts are checked
+//! against the desks it draws and against the optimum, see [Catalog::miscounts].
+
+use std::fmt::{Display, Formatter};
+use std::path::Path;
+
+use eyre::WrapErr;
+use itertools::Itertools;
+use serde::{Deserialize, Serialize};
+
+use crate::bound::{self, Bound};
+use crate::budget::{Budget, Meter};
+use crate::exact;
+use crate::layout::Layout;
+use crate::levels::level5;
+use crate::room::{DeskShape, FloorPlan, Rules};
+use crate::sat;
+
+/// The most desks found for a room, stored for the orientation with `width <= height`
+#[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
+pub struct Entry {
+    pub width: usize,
+    pub height: usize,
+    pub desks: usize,
+    /// Whether no layout with more desks exists
+    pub optimal: bool,
+    /// The level5 strategy that places the most desks and how many it places
+    pub strategy: String,
+    pub strategy_desks: usize,
+    /// The desks as written by [Layout::render]
+    pub layout: String,
+}
+
+#[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
+pub struct Catalog {
+    /// The desk size and the rule keywords, as on a room line
+    pub desk: String,
+    pub rules: String,
+    pub entries: Vec<Entry>,
+    #[serde(default)]
+    pub formulas: Vec<Formula>,
+}
+
+impl Catalog {
+    pub fn read(path: impl AsRef<Path>) -> eyre::Result<Self> {
+        let path = path.as_ref();
+        let content = std::fs::read_to_string(path).wrap_err(format!("Cannot read '{}'", path.display()))?;
+        serde_json::from_str(&content).wrap_err(format!("Invalid catalog '{}'", path.display()))
+    }
+
+    pub fn write(&self, path: impl AsRef<Path>) -> eyre::Result<()> {
+        let path = path.as_ref();
+        let content = serde_json::to_string_pretty(self).wrap_err("Failed to serialize catalog")?;
+        std::fs::write(path, content).wrap_err(format!("Failed to write '{}'", path.display()))
+    }
+
+    /// The entry for a room in either orientation
+    pub fn entry(&self, width: usize, height: usize) -> Option<&Entry> {
+        let (width, height) = (width.min(height), width.max(height));
+        self.entries.iter().find(|entry| (entry.width, entry.height) == (width, height))
+    }
+
+    /// The formula that holds for a room, if one was mined for its class
+    pub fn formula(&self, width: usize, height: usize) -> Option<&Formula> {
+        self.formulas.iter().find(|formula| {
+            let class = (width % formula.modulus, height % formula.modulus);
+            class == (formula.width_rest, formula.height_rest) && width.min(height) >= formula.min_side
+        })
+    }
+
+    /// The rooms of an even width in which the even width pattern of level5 counts other desks
+    /// than it draws, draws desks too close to each other or counts more desks than fit
+    ///
+    /// The pattern is only known for 2x1 desks without further rules, other catalogs have no
+    /// miscounts.
+    pub fn miscounts(&self) -> Vec<Miscount> {
+        if (self.desk.as_str(), &self.rules) != ("2x1", &Rules::default().to_string()) {
+            return Vec::new();
+        }
+
+        self.entries.iter()
+            .flat_map(|entry| [(entry.width, entry.height, entry), (entry.height, entry.width, entry)])
+            .filter(|(width, _, _)| width % 2 == 0)
+            .unique_by(|(width, height, _)| (*width, *height))
+            .filter_map(|(width, height, entry)| {
+                let room = level5::Room::new(width, height, 0, level5::DESK, Rules::default());
+                let (matrix, counted) = room.generate_room_table_matrix_for_even_width().ok()?;
+                let layout = Layout::parse_marks(width, height, &matrix).ok()?;
+                let spaced = layout.check_shape(level5::DESK).is_ok() && layout.check_spacing().is_ok();
+                let miscount = Miscount {
+                    width, height, counted,
+                    drawn: layout.len(),
+                    spaced,
+                    desks: entry.desks,
+                    formula: self.formula(width, height).map(|formula| formula.desks(width, height)),
+                };
+                (counted != miscount.drawn || !spaced || counted > entry.desks).then_some(miscount)
+            })
+            .collect()
+    }
+
+    /// The rooms in which the level5 strategies place fewer desks than fit
+    pub fn shortfalls(&self) -> impl Iterator<Item = &Entry> {
+        self.entries.iter().filter(|entry| entry.strategy_desks < entry.desks)
+    }
+}
+
+/// A room in which the arithmetic of [level5::Room::generate_room_table_matrix_for_even_width]
+/// disagrees with its pattern or with the catalog
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Miscount {
+    pub width: usize,
+    pub height: usize,
+    /// The desks counted by the arithmetic and drawn into the pattern
+    pub counted: usize,
+    pub drawn: usize,
+    /// Whether the drawn desks are 2x1 desks one cell apart
+    pub spaced: bool,
+    /// The most desks that fit, and the desks the mined formula of the room predicts
+    pub desks: usize,
+    pub formula: Option<i64>,
+}
+
+impl Display for Miscount {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let Miscount { width, height, counted, drawn, spaced, desks, formula } = self;
+        write!(f, "The even width pattern counts {counted} desks in {width}x{height} but draws {drawn}")?;
+        if !spaced {
+            write!(f, " that are not one cell apart")?;
+        }
+        write!(f, ", {desks} fit")?;
+        match formula {
+            Some(formula) => write!(f, " and the formula predicts {formula}"),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Solves every room with `1 <= width <= height <= max`, spending the budget on each
+///
+/// Optimal entries of a `known` catalog for the same desks and rules are kept, so an
+/// interrupted or smaller sweep can be extended.
+pub fn sweep(max: usize, desk: DeskShape, rules: Rules, budget: Budget, known: Option<&Catalog>) -> Catalog {
+    let mut catalog = Catalog { desk: desk.to_string(), rules: rules.to_string(), entries: Vec::new(), formulas: Vec::new() };
+    let known = known.filter(|known| (&known.desk, &known.rules) == (&catalog.desk, &catalog.rules));
+
+    for height in 1..=max {
+        for width in 1..=height {
+            let entry = match known.and_then(|known| known.entry(width, height)).filter(|entry| entry.optimal) {
+                Some(entry) => entry.clone(),
+                None => solve(width, height, desk, rules, budget),
+            };
+            catalog.entries.push(entry);
+        }
+    }
+
+    catalog
+}
+
+/// Finds the most desks for a single room
+pub fn solve(width: usize, height: usize, desk: DeskShape, rules: Rules, budget: Budget) -> Entry {
+    let _span = tracing::debug_span!("room", width, height).entered();
+    let plan = FloorPlan { rules, ..FloorPlan::open(width, height, 0, desk) };
+    let (strategy, layout) = level5::strategies(&plan);
+    let strategy_desks = layout.len();
+
+    let meter = budget.start();
+    let upper = bound::maximum(&plan.floor, plan.desk, plan.rules.spacing, &meter.sibling());
+    let (best, optimal) = maximise(&plan, layout, upper, &meter);
+    tracing::debug!(desks = best.len(), optimal, strategy_desks, "Room solved");
+    Entry { width, height, desks: best.len(), optimal, strategy: strategy.to_string(), strategy_desks, layout: best.render() }
+}
+
+/// Improves a layout of a floor plan until it holds the most desks that fit, returning whether
+/// no layout holds more
+///
+/// Small rooms are searched exhaustively, larger ones ask the placement formula for one desk
+/// more until it is unsatisfiable, the `upper` bound is reached or the budget runs out.
+pub fn maximise(plan: &FloorPlan, mut best: Layout, upper: Bound, meter: &Meter) -> (Layout, bool) {
+    let room = Layout::with_floor(&plan.floor).under(plan.rules);
+    let cells = plan.width * plan.height;
+
+    let mut optimal = best.len() >= upper.desks;
+    if !optimal && cells <= exact::CELL_LIMIT {
+        let layout = exact::maximise(&room, plan.desk, meter);
+        if layout.len() > best.len() {
+            best = layout;
+        }
+        optimal = !meter.is_exhausted();
+    }
+
+    while !optimal && cells <= sat::CELL_LIMIT {
+        match sat::place(&room, plan.desk, best.len() + 1, meter) {
+            Some(layout) => best = layout,
+            None if meter.is_exhausted() => break,
+            None => optimal = true,
+        }
+        optimal |= best.len() >= upper.desks;
+    }
+
+    (best, optimal)
+}
+
+/// The desks in the rooms of one remainder class, as a bilinear polynomial in `w / k` and
+/// `h / k` rounded down, which holds for rooms whose sides are at least `min_side` long
+#[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
+pub struct Formula {
+    pub modulus: usize,
+    pub width_rest: usize,
+    pub height_rest: usize,
+    pub min_side: usize,
+    /// The constant, the factors of `w / k` and `h / k` and the one of their product
+    pub coefficients: [i64; 4],
+    /// How many optimal rooms the formula was checked against
+    pub rooms: usize,
+}
+
+impl Formula {
+    pub fn desks(&self, width: usize, height: usize) -> i64 {
+        let (w, h) = ((width / self.modulus) as i64, (height / self.modulus) as i64);
+        let [constant, per_width, per_height, per_area] = self.coefficients;
+        constant + per_width * w + per_height * h + per_area * w * h
+    }
+
+    /// Fits the formula through four rooms next to each other in the class and checks it
+    /// against every other room, for the smallest side length that leaves at least one room
+    /// to check
+    fn fit(modulus: usize, width_rest: usize, height_rest: usize, points: &[(usize, usize, usize)]) -> Option<Self> {
+        let sides = points.iter().map(|(width, height, _)| *width.min(height)).sorted_unstable().dedup();
+
+        sides.into_iter().find_map(|min_side| {
+            let points = points.iter().filter(|(width, height, _)| *width.min(height) >= min_side).collect::<Vec<_>>();
+            let desks = |w: usize, h: usize| {
+                points.iter().find(|(width, height, _)| (width / modulus, height / modulus) == (w, h)).map(|point| point.2 as i64)
+            };
+
+            let w = points.iter().map(|(width, _, _)| width / modulus).min()?;
+            let h = points.iter().filter(|(width, _, _)| width / modulus == w).map(|(_, height, _)| height / modulus).min()?;
+            let corner = desks(w, h)?;
+            let along_width = desks(w + 1, h)? - corner;
+            let along_height = desks(w, h + 1)? - corner;
+            let per_area = desks(w + 1, h + 1)? - corner - along_width - along_height;
+
+            let (w, h) = (w as i64, h as i64);
+            let per_width = along_width - per_area * h;
+            let per_height = along_height - per_area * w;
+            let constant = corner - per_width * w - per_height * h - per_area * w * h;
+
+            let formula = Formula {
+                modulus, width_rest, height_rest, min_side,
+                coefficients: [constant, per_width, per_height, per_area],
+                rooms: points.len(),
+            };
+            let fits = points.iter().all(|(width, height, desks)| formula.desks(*width, *height) == *desks as i64);
+            (points.len() > 4 && fits).then_some(formula)
+        })
+    }
+}
+
+impl Display for Formula {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let Formula { modulus: k, width_rest, height_rest, min_side, coefficients, rooms } = self;
+        let terms = coefficients.iter()
+            .zip(["", "W", "H", "W*H"])
+            .filter(|(coefficient, term)| **coefficient != 0 || (term.is_empty() && coefficients.iter().all(|c| *c == 0)))
+            .map(|(coefficient, term)| match (coefficient, term) {
+                (coefficient, "") => coefficient.to_string(),
+                (1, term) => term.to_string(),
+                (-1, term) => format!("-{term}"),
+                (coefficient, term) => format!("{coefficient}*{term}"),
+            })
+            .join(" + ")
+            .replace("+ -", "- ");
+
+        write!(
+            f,
+            "w % {k} = {width_rest}, h % {k} = {height_rest}, sides >= {min_side}: {terms} \
+             with W = w / {k}, H = h / {k} ({rooms} rooms)",
+        )
+    }
+}
+
+/// Tries the moduli up to `max_modulus` and returns the formulas of the smallest one that has a
+/// formula for every remainder class, or of the one that covers the most classes
+pub fn mine(entries: &[Entry], max_modulus: usize) -> Vec<Formula> {
+    let points = entries.iter()
+        .filter(|entry| entry.optimal)
+        .flat_map(|entry| [(entry.width, entry.height, entry.desks), (entry.height, entry.width, entry.desks)])
+        .unique()
+        .collect::<Vec<_>>();
+
+    let mut best: Vec<Formula> = Vec::new();
+    for modulus in 1..=max_modulus {
+        let formulas = (0..modulus)
+            .cartesian_product(0..modulus)
+            .filter_map(|(width_rest, height_rest)| {
+                let class = points.iter()
+                    .filter(|(width, height, _)| width % modulus == width_rest && height % modulus == height_rest)
+                    .copied()
+                    .collect::<Vec<_>>();
+                Formula::fit(modulus, width_rest, height_rest, &class)
+            })
+            .collect::<Vec<_>>();
+        tracing::debug!(modulus, classes = formulas.len(), "Mined formulas");
+
+        let complete = formulas.len() == modulus * modulus;
+        if complete || formulas.len() > best.len() {
+            best = formulas;
+        }
+        if complete {
+            break;
+        }
+    }
+
+    best
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{mine, sweep};
+    use crate::budget::Budget;
+    use crate::layout::Layout;
+    use crate::room::{DeskShape, Rules};
+
+    #[test]
+    pub fn test_sweep() {
+        let catalog = sweep(8, DeskShape::new(2, 1), Rules::default(), Budget::default(), None);
+        assert_eq!(catalog.entries.len(), 36);
+        assert!(catalog.entries.iter().all(|entry| entry.optimal && entry.strategy_desks <= entry.desks));
+        assert_eq!(catalog.entry(3, 3).map(|entry| entry.desks), Some(2));
+        assert_eq!(catalog.entry(7, 4).map(|entry| entry.desks), Some(6));
+
+        let entry = catalog.entry(8, 6).unwrap();
+        let layout = Layout::parse_marks(entry.width, entry.height, &entry.layout).unwrap();
+        layout.check_spacing().unwrap();
+        assert_eq!(layout.len(), entry.desks);
+
+        let formulas = mine(&catalog.entries, 2);
+        assert!(!formulas.is_empty());
+        for formula in &formulas {
+            for entry in &catalog.entries {
+                for (width, height) in [(entry.width, entry.height), (entry.height, entry.width)] {
+                    let class = (width % formula.modulus, height % formula.modulus);
+                    if class == (formula.width_rest, formula.height_rest) && width.min(height) >= formula.min_side {
+                        assert_eq!(formula.desks(width, height), entry.desks as i64, "{formula} for {width}x{height}");
+                    }
+                }
+            }
+        }
+
+        let extended = sweep(9, DeskShape::new(2, 1), Rules::default(), Budget { nodes: Some(0), ..Budget::default() }, Some(&catalog));
+        assert_eq!(extended.entries[..36], catalog.entries[..]);
+    }
+
+    #[test]
+    pub fn test_miscounts() {
+        let mut catalog = sweep(8, DeskShape::new(2, 1), Rules::default(), Budget::default(), None);
+        catalog.formulas = mine(&catalog.entries, 2);
+        assert_eq!(catalog.miscounts(), []);
+
+        let mut miscounted = catalog.clone();
+        let entry = miscounted.entries.iter_mut().find(|entry| (entry.width, entry.height) == (6, 7)).unwrap();
+        entry.desks = 7;
+        let [miscount] = &miscounted.miscounts()[..] else { panic!("{:?}", miscounted.miscounts()) };
+        assert_eq!((miscount.width, miscount.height, miscount.counted, miscount.drawn), (6, 7, 8, 8));
+
+        let packed = sweep(4, DeskShape::new(3, 1), Rules::default(), Budget::default(), None);
+        assert!(packed.miscounts().is_empty());
+    }
+}
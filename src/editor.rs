@@ -0,0 +1,366 @@
+//! A terminal editor for the desks of a level5 room, verifying the layout on every change
+//!
+//! Like the brush of the visualizer, the cursor carries a desk that the space bar places or
+//! removes and `r` rotates. After every change the layout runs through the level5 verifier,
+//! and the editor shows the desk count against the required one and the bound, and marks the
+//! desks that break the spacing. Saving writes the room into its `.out` file, keeping the
+//! outputs of the other rooms of the input.
+
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use crossterm::event::{self, Event, KeyCode, KeyEventKind, KeyModifiers};
+use crossterm::style::{Color, Print, ResetColor, SetBackgroundColor, SetForegroundColor};
+use crossterm::{cursor, execute, queue, terminal};
+use eyre::{eyre, WrapErr};
+use itertools::Itertools;
+
+use crate::bound::{self, Bound};
+use crate::budget::Meter;
+use crate::input::Input;
+use crate::layout::{split_rooms, Desk, Layout};
+use crate::levels::{self, level5};
+use crate::output::Verification;
+use crate::room::FloorPlan;
+
+/// What the verifier and the spacing check say about a layout
+pub struct Check {
+    /// The desks that break the spacing to another desk
+    pub conflicts: Vec<Desk>,
+    pub verification: eyre::Result<Verification>,
+}
+
+pub struct Editor {
+    pub plan: FloorPlan,
+    pub layout: Layout,
+    pub cursor: (usize, usize),
+    pub vertical: bool,
+    bound: Bound,
+    /// The room of the input being edited, starting at 0, and how many rooms the input has
+    room: usize,
+    rooms: usize,
+    output: PathBuf,
+    message: String,
+}
+
+impl Editor {
+    /// Edits room `room` of `rooms` rooms, starting from the desks already in the output file
+    pub fn new(plan: FloorPlan, room: usize, rooms: usize, output: PathBuf) -> Self {
+        let mut layout = Layout::with_floor(&plan.floor).under(plan.rules);
+        let existing = std::fs::read_to_string(&output).ok()
+            .and_then(|content| split_rooms(&content).nth(room))
+            .and_then(|output| Layout::parse_rendered(plan.width, plan.height, &output).ok());
+        if let Some(existing) = existing {
+            layout.desks = existing.desks;
+        }
+
+        let bound = bound::maximum(&plan.floor, plan.desk, plan.rules.spacing, &Meter::default());
+        Editor { plan, layout, cursor: (0, 0), vertical: false, bound, room, rooms, output, message: String::new() }
+    }
+
+    /// Loads a room line, or a room of a level5 input file whose output defaults to the one of
+    /// its task
+    pub fn load(room: &[String], input: Option<&Path>, subtask: usize, output: Option<PathBuf>) -> eyre::Result<Self> {
+        let Some(input) = input else {
+            let plan = room.join(" ").parse::<FloorPlan>().wrap_err("Invalid room")?;
+            check_size(&plan)?;
+            let output = output.unwrap_or_else(|| {
+                Path::new(levels::OUTPUT_DIR).join(format!("{}x{}.out", plan.width, plan.height))
+            });
+            return Ok(Editor::new(plan, 0, 1, output));
+        };
+
+        let content = std::fs::read_to_string(input).wrap_err(format!("Failed to read {}", input.display()))?;
+        let rooms = content.parse::<level5::Input>().wrap_err("Invalid rooms")?;
+        let count = rooms.subtasks().count();
+        let plan = rooms.subtasks()
+            .nth(subtask.wrapping_sub(1))
+            .ok_or(eyre!("The input has {count} rooms, there is no room {subtask}"))?
+            .plan();
+        check_size(&plan)?;
+
+        let output = match output {
+            Some(output) => output,
+            None => {
+                let stem = input.file_stem().unwrap_or_default().to_string_lossy();
+                match stem.strip_prefix("level5_") {
+                    Some(task) => levels::find(5)?.output_path(task),
+                    None => input.with_extension("out"),
+                }
+            }
+        };
+        Ok(Editor::new(plan, subtask - 1, count, output))
+    }
+
+    /// The desk the cursor would place
+    pub fn brush(&self) -> Desk {
+        let size = if self.vertical { self.plan.desk.vertical() } else { self.plan.desk.horizontal() };
+        Desk::new(self.cursor.0, self.cursor.1, size)
+    }
+
+    fn desk_at(&self, x: usize, y: usize) -> Option<usize> {
+        self.layout.desks.iter().position(|desk| desk.cells().contains(&(x, y)))
+    }
+
+    pub fn move_by(&mut self, dx: isize, dy: isize) {
+        let (x, y) = self.cursor;
+        self.cursor = (
+            x.saturating_add_signed(dx).min(self.plan.width - 1),
+            y.saturating_add_signed(dy).min(self.plan.height - 1),
+        );
+    }
+
+    pub fn rotate(&mut self) {
+        self.vertical = !self.vertical;
+    }
+
+    /// Removes the desk under the cursor, or places the brush if it fits into the room without
+    /// overlapping another desk
+    pub fn toggle(&mut self) {
+        if let Some(index) = self.desk_at(self.cursor.0, self.cursor.1) {
+            self.layout.desks.remove(index);
+            return;
+        }
+
+        let brush = self.brush();
+        let inside = brush.x + brush.width <= self.plan.width && brush.y + brush.height <= self.plan.height;
+        if inside && brush.cells().all(|(x, y)| self.desk_at(x, y).is_none()) {
+            self.layout.place(brush);
+        } else {
+            self.message = "The desk does not fit there".to_string();
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.layout.desks.clear();
+    }
+
+    pub fn check(&self) -> Check {
+        let desks = self.layout.desks.iter()
+            .copied()
+            .sorted_by_key(|desk| (desk.y, desk.x))
+            .collect::<Vec<_>>();
+        let conflicts = self.layout.conflicts(&desks)
+            .into_iter()
+            .flat_map(|(a, b)| [desks[a], desks[b]])
+            .unique()
+            .collect();
+
+        Check { conflicts, verification: level5::verify_plan(&self.plan, &self.layout.render()) }
+    }
+
+    /// The output file with this room replaced by the layout
+    ///
+    /// The other rooms keep their output, so an input with several rooms needs an output for
+    /// all of them, e.g. from a run of the level.
+    pub fn merged_output(&self) -> eyre::Result<String> {
+        let mut rooms = match std::fs::read_to_string(&self.output) {
+            Ok(content) => split_rooms(&content).map(|room| room.lines().join("\r\n")).collect(),
+            Err(_) => Vec::new(),
+        };
+        if rooms.len() != self.rooms {
+            if self.rooms > 1 {
+                return Err(eyre!(
+                    "'{}' has outputs for {} of the {} rooms, run the level to write the others first",
+                    self.output.display(), rooms.len(), self.rooms,
+                ));
+            }
+            rooms = vec![String::new()];
+        }
+
+        rooms[self.room] = self.layout.render();
+        Ok(level5::reduce(rooms))
+    }
+
+    /// Writes the output file if the layout passes verification
+    pub fn save(&self) -> eyre::Result<()> {
+        self.check().verification.wrap_err("Not saved, the layout fails verification")?;
+        let content = self.merged_output()?;
+        if let Some(parent) = self.output.parent() {
+            std::fs::create_dir_all(parent).wrap_err(format!("Cannot create '{}'", parent.display()))?;
+        }
+        std::fs::write(&self.output, content).wrap_err(format!("Failed to write '{}'", self.output.display()))
+    }
+
+    /// Edits the room until `q` or escape is pressed, restoring the terminal afterwards
+    pub fn run(mut self) -> eyre::Result<()> {
+        let mut stdout = std::io::stdout();
+        terminal::enable_raw_mode().wrap_err("Cannot switch the terminal to raw mode")?;
+        execute!(stdout, terminal::EnterAlternateScreen, cursor::Hide)?;
+
+        let result = self.edit(&mut stdout);
+
+        execute!(stdout, ResetColor, cursor::Show, terminal::LeaveAlternateScreen)?;
+        terminal::disable_raw_mode()?;
+        result
+    }
+
+    fn edit(&mut self, stdout: &mut impl Write) -> eyre::Result<()> {
+        loop {
+            self.draw(stdout)?;
+
+            let Event::Key(key) = event::read()? else { continue };
+            if key.kind != KeyEventKind::Press {
+                continue;
+            }
+            self.message.clear();
+
+            match key.code {
+                KeyCode::Char('q') | KeyCode::Esc => return Ok(()),
+                KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => return Ok(()),
+                KeyCode::Left | KeyCode::Char('h') => self.move_by(-1, 0),
+                KeyCode::Right | KeyCode::Char('l') => self.move_by(1, 0),
+                KeyCode::Up | KeyCode::Char('k') => self.move_by(0, -1),
+                KeyCode::Down | KeyCode::Char('j') => self.move_by(0, 1),
+                KeyCode::Char(' ') | KeyCode::Enter => self.toggle(),
+                KeyCode::Char('r') => self.rotate(),
+                KeyCode::Delete | KeyCode::Char('x') => {
+                    if let Some(index) = self.desk_at(self.cursor.0, self.cursor.1) {
+                        self.layout.desks.remove(index);
+                    }
+                }
+                KeyCode::Char('c') => self.clear(),
+                KeyCode::Char('s') => {
+                    self.message = match self.save() {
+                        Ok(()) => format!("Saved '{}'", self.output.display()),
+                        Err(error) => format!("{error:#}"),
+                    };
+                }
+                _ => {}
+            }
+        }
+    }
+
+    fn draw(&self, stdout: &mut impl Write) -> eyre::Result<()> {
+        let Check { conflicts, verification } = self.check();
+        let (columns, rows) = terminal::size()?;
+        let status_rows = 5;
+
+        // Scrolls the grid so that the cursor stays visible, every cell is two columns wide
+        let visible = ((columns as usize / 2).max(1), (rows as usize).saturating_sub(status_rows).max(1));
+        let offset = |cursor: usize, visible: usize, length: usize| {
+            cursor.saturating_sub(visible / 2).min(length.saturating_sub(visible))
+        };
+        let (left, top) = (offset(self.cursor.0, visible.0, self.plan.width), offset(self.cursor.1, visible.1, self.plan.height));
+
+        let brush = self.brush();
+        let grid = self.layout.grid();
+        queue!(stdout, cursor::MoveTo(0, 0))?;
+        for y in top..(top + visible.1).min(self.plan.height) {
+            for x in left..(left + visible.0).min(self.plan.width) {
+                let desk = grid[y * self.plan.width + x];
+                let (cell, color) = match desk {
+                    0 if self.layout.is_blocked(x, y) => ("##", Color::DarkGrey),
+                    0 => (". ", Color::Reset),
+                    desk if conflicts.contains(&self.layout.desks[desk - 1]) => ("██", Color::Red),
+                    _ => ("██", Color::Green),
+                };
+                let background = if (x, y) == self.cursor {
+                    Color::Yellow
+                } else if brush.cells().contains(&(x, y)) {
+                    Color::DarkCyan
+                } else {
+                    Color::Reset
+                };
+                queue!(stdout, SetForegroundColor(color), SetBackgroundColor(background), Print(cell), ResetColor)?;
+            }
+            queue!(stdout, terminal::Clear(terminal::ClearType::UntilNewLine), Print("\r\n"))?;
+        }
+
+        let FloorPlan { width, height, desk_count, desk, rules, .. } = &self.plan;
+        let verdict = match &verification {
+            Ok(_) => "passed".to_string(),
+            Err(error) => format!("{error:#}").replace(['\r', '\n'], " "),
+        };
+        let lines = [
+            format!("{width}x{height} room of {desk} desks under {rules}, room {} of {} -> {}", self.room + 1, self.rooms, self.output.display()),
+            format!("Desks {} of {desk_count} required, bound {}, {} desks break the spacing", self.layout.len(), self.bound, conflicts.len()),
+            format!("Verifier: {verdict}"),
+            self.message.clone(),
+            "Arrows move, space places or removes, r rotates, x removes, c clears, s saves, q quits".to_string(),
+        ];
+        for line in lines {
+            let line = line.chars().take(columns as usize).collect::<String>();
+            queue!(stdout, Print(line), terminal::Clear(terminal::ClearType::UntilNewLine), Print("\r\n"))?;
+        }
+        queue!(stdout, terminal::Clear(terminal::ClearType::FromCursorDown))?;
+
+        stdout.flush()?;
+        Ok(())
+    }
+}
+
+/// Rejects rooms without cells, which leave the cursor no cell to stand on
+fn check_size(plan: &FloorPlan) -> eyre::Result<()> {
+    if plan.width == 0 || plan.height == 0 {
+        return Err(eyre!("The {}x{} room has no cells to place desks on", plan.width, plan.height));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Editor;
+    use crate::room::FloorPlan;
+
+    #[test]
+    pub fn test_empty_rooms() {
+        for room in ["0 3 0", "4 0 0"] {
+            let room = room.split(' ').map(str::to_string).collect::<Vec<_>>();
+            let error = Editor::load(&room, None, 1, None).err().unwrap();
+            assert!(error.to_string().ends_with("room has no cells to place desks on"), "{error}");
+        }
+    }
+
+    #[test]
+    pub fn test_edit() {
+        let output = std::env::temp_dir().join(format!("editor_test_{}.out", std::process::id()));
+        let plan = "4 3 2".parse::<FloorPlan>().unwrap();
+        let mut editor = Editor::new(plan.clone(), 0, 1, output.clone());
+
+        editor.toggle();
+        editor.move_by(2, 0);
+        editor.toggle();
+        let check = editor.check();
+        assert_eq!(check.conflicts.len(), 2);
+        assert!(check.verification.is_err());
+        assert!(editor.save().is_err());
+
+        editor.toggle();
+        editor.move_by(5, 5);
+        editor.rotate();
+        editor.toggle();
+        assert_eq!(editor.layout.len(), 1, "The vertical desk sticks out of the room");
+        editor.move_by(0, -1);
+        editor.toggle();
+        assert!(editor.check().conflicts.is_empty());
+        editor.save().unwrap();
+        assert_eq!(std::fs::read_to_string(&output).unwrap(), "XX..\r\n...X\r\n...X\r\n\r\n");
+
+        let reopened = Editor::new(plan.clone(), 0, 1, output.clone());
+        assert_eq!(reopened.layout.len(), 2);
+        let error = Editor::new(plan, 1, 2, output.clone()).merged_output().unwrap_err();
+        assert!(error.to_string().contains("outputs for 1 of the 2 rooms"), "{error}");
+
+        std::fs::remove_file(output).unwrap();
+    }
+
+    #[test]
+    pub fn test_edit_touching_desks() {
+        let output = std::env::temp_dir().join(format!("editor_touching_test_{}.out", std::process::id()));
+        let plan = "4 1 2 2x1 chebyshev:0".parse::<FloorPlan>().unwrap();
+        let mut editor = Editor::new(plan.clone(), 0, 1, output.clone());
+
+        editor.toggle();
+        editor.move_by(2, 0);
+        editor.toggle();
+        assert!(editor.check().conflicts.is_empty());
+        editor.save().unwrap();
+        assert_eq!(std::fs::read_to_string(&output).unwrap(), "1 1 2 2\r\n\r\n");
+
+        let reopened = Editor::new(plan, 0, 1, output.clone());
+        assert_eq!(reopened.layout.len(), 2);
+
+        std::fs::remove_file(output).unwrap();
+    }
+}
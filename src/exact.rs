@@ -0,0 +1,150 @@
+//! Exhaustive search for the most desks that fit into a room
+
+use crate::bound;
+use crate::budget::Meter;
+use crate::layout::{Desk, Layout};
+use crate::room::DeskShape;
+
+/// Rooms with up to this many cells are small enough to be searched exhaustively
+pub const CELL_LIMIT: usize = 48;
+
+/// Finds a layout with the most desks of the given shape that can be added to `room`
+///
+/// The search follows the spacing, floor and other rules of the room. It decides for every
+/// cell in row-major order whether a desk starts there, and prunes branches that can not beat
+/// the best layout found so far. If the budget runs out, that layout is returned.
+pub fn maximise(room: &Layout, shape: DeskShape, meter: &Meter) -> Layout {
+    let mut search = Search {
+        shape,
+        meter,
+        grid: room.grid(),
+        layout: room.clone(),
+        best: room.clone(),
+        open_after: open_after(room),
+    };
+    search.branch(0);
+    search.best
+}
+
+/// The number of cells that are not blocked from each index on
+fn open_after(room: &Layout) -> Vec<usize> {
+    let cells = room.width * room.height;
+    let mut open_after = vec![0; cells + 1];
+    for index in (0..cells).rev() {
+        open_after[index] = open_after[index + 1] + usize::from(!room.is_blocked(index % room.width, index / room.width));
+    }
+    open_after
+}
+
+struct Search<'a> {
+    shape: DeskShape,
+    meter: &'a Meter,
+    layout: Layout,
+    grid: Vec<usize>,
+    best: Layout,
+    /// The number of cells that are not blocked from each index on
+    open_after: Vec<usize>,
+}
+
+impl Search<'_> {
+    fn branch(&mut self, start: usize) {
+        if self.layout.len() > self.best.len() && self.layout.follows_rules() {
+            self.best = self.layout.clone();
+        }
+        if !self.meter.spend() {
+            return;
+        }
+
+        let width = self.layout.width;
+        let Some(index) = (start..self.grid.len())
+            .find(|index| self.grid[*index] == 0 && !self.layout.is_blocked(index % width, index / width))
+        else {
+            return;
+        };
+        if self.layout.len() + self.bound(index) <= self.best.len() {
+            return;
+        }
+
+        for size in self.shape.orientations() {
+            let desk = Desk::new(index % width, index / width, size);
+            if self.layout.is_free(&self.grid, &desk) {
+                self.layout.place(desk);
+                self.mark(&desk, self.layout.len());
+                self.branch(index + 1);
+                self.mark(&desk, 0);
+                self.layout.desks.pop();
+            }
+        }
+
+        self.branch(index + 1);
+    }
+
+    fn mark(&mut self, desk: &Desk, id: usize) {
+        for (x, y) in desk.cells() {
+            self.grid[y * self.layout.width + x] = id;
+        }
+    }
+
+    /// An upper bound for the number of desks starting at `index` or later
+    ///
+    /// Such desks lie within the open cells from `index` on, which are all in the rows from
+    /// the one of `index` on, so they are bounded by [bound::counting] on those rows.
+    fn bound(&self, index: usize) -> usize {
+        let (width, rows) = (self.layout.width, self.layout.height - index / self.layout.width);
+        bound::counting(self.open_after[index], width, rows, self.shape, self.layout.rules().spacing)
+    }
+}
+
+/// Finds a layout that adds the given number of desks of every shape to `room`
+///
+/// Like [maximise], but returns `None` as soon as the open cells left can not hold the desks
+/// still missing, or if the budget runs out before the desks are placed.
+pub fn place_all(room: &Layout, desks: &[(DeskShape, usize)], meter: &Meter) -> Option<Layout> {
+    fn fill(layout: &mut Layout, grid: &mut [usize], start: usize, missing: &mut [(DeskShape, usize)], open_after: &[usize], meter: &Meter) -> bool {
+        if missing.iter().all(|(_, count)| *count == 0) {
+            return layout.follows_rules();
+        }
+        if !meter.spend() {
+            return false;
+        }
+
+        let width = layout.width;
+        let Some(index) = (start..grid.len()).find(|index| grid[*index] == 0 && !layout.is_blocked(index % width, index / width)) else {
+            return false;
+        };
+        let area = missing.iter().map(|(shape, count)| shape.length * shape.thickness * count).sum::<usize>();
+        if area > open_after[index] {
+            return false;
+        }
+
+        for kind in 0..missing.len() {
+            let (shape, count) = missing[kind];
+            if count == 0 {
+                continue;
+            }
+
+            for size in shape.orientations() {
+                let desk = Desk::new(index % width, index / width, size);
+                if layout.is_free(grid, &desk) {
+                    layout.place(desk);
+                    desk.cells().for_each(|(x, y)| grid[y * width + x] = layout.len());
+                    missing[kind].1 -= 1;
+
+                    if fill(layout, grid, index + 1, missing, open_after, meter) {
+                        return true;
+                    }
+
+                    missing[kind].1 += 1;
+                    desk.cells().for_each(|(x, y)| grid[y * width + x] = 0);
+                    layout.desks.pop();
+                }
+            }
+        }
+
+        fill(layout, grid, index + 1, missing, open_after, meter)
+    }
+
+    let mut layout = room.clone();
+    let mut missing = desks.to_vec();
+    fill(&mut layout, &mut room.grid(), 0, &mut missing, &open_after(room), meter).then_some(layout)
+}
@@ -0,0 +1,169 @@
+//! Submission archives of the verified outputs of a level and the sources
+
+use std::io::{Cursor, Seek, Write};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use color_eyre::Help;
+use eyre::{eyre, WrapErr};
+use serde::Serialize;
+use zip::write::SimpleFileOptions;
+use zip::ZipWriter;
+
+use crate::levels::{Level, OUTPUT_DIR};
+use crate::report::{LevelReport, RunReport, TaskReport};
+use crate::summary::Table;
+
+const SOURCE_DIR: &str = concat!(::location_macros::workspace_dir!(), "/src/");
+
+#[derive(Serialize)]
+struct Manifest {
+    level: usize,
+    name: &'static str,
+    commit: Option<String>,
+    dirty: bool,
+    created_at: u64,
+    tasks: Vec<TaskReport>,
+}
+
+fn git(args: &[&str]) -> Option<String> {
+    let output = Command::new("git")
+        .args(args)
+        .current_dir(::location_macros::workspace_dir!())
+        .output()
+        .ok()?;
+
+    output.status.success()
+        .then(|| String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+fn source_files(dir: &Path, files: &mut Vec<PathBuf>) -> eyre::Result<()> {
+    let entries = std::fs::read_dir(dir).wrap_err(format!("Cannot read '{}'", dir.display()))?;
+    for entry in entries {
+        let path = entry.wrap_err(format!("Cannot read '{}'", dir.display()))?.path();
+        if path.is_dir() {
+            source_files(&path, files)?;
+        } else {
+            files.push(path);
+        }
+    }
+    Ok(())
+}
+
+fn add_file<W: Write + Seek>(zip: &mut ZipWriter<W>, name: &str, content: &[u8]) -> eyre::Result<()> {
+    zip.start_file(name, SimpleFileOptions::default())
+        .wrap_err(format!("Cannot add '{name}' to archive"))?;
+    zip.write_all(content).wrap_err(format!("Cannot write '{name}' to archive"))
+}
+
+/// Zips the `src` directory, with paths relative to it
+fn source_archive() -> eyre::Result<Vec<u8>> {
+    let mut files = Vec::new();
+    source_files(Path::new(SOURCE_DIR), &mut files)?;
+    files.sort();
+
+    let mut zip = ZipWriter::new(Cursor::new(Vec::new()));
+    for file in files {
+        let name = file.strip_prefix(SOURCE_DIR)?.to_string_lossy().replace('\\', "/");
+        let content = std::fs::read(&file).wrap_err(format!("Cannot read '{}'", file.display()))?;
+        add_file(&mut zip, &format!("src/{name}"), &content)?;
+    }
+
+    Ok(zip.finish().wrap_err("Cannot finish source archive")?.into_inner())
+}
+
+/// Bundles the verified outputs of a level with a snapshot of the sources for submission
+///
+/// The outputs are read from `outputs`, `out/levelN` by default. The archive contains every
+/// `levelN_k.out`, a `source.zip` with the `src` directory and
+/// a `manifest.json` with the verification results and the git commit the outputs stem from.
+/// Nothing is written if any task output is missing or fails verification.
+pub fn export(level: &Level, outputs: Option<PathBuf>, archive: Option<PathBuf>) -> eyre::Result<PathBuf> {
+    let _span = tracing::info_span!("export", level = level.name).entered();
+    let dir = outputs.unwrap_or_else(|| Path::new(OUTPUT_DIR).join(level.name));
+    let mut report = LevelReport::new(level.number, level.name);
+    let mut outputs = Vec::new();
+
+    for (task, name) in level.tasks.iter().enumerate() {
+        let path = level.output_path_in(&dir, name);
+        let (task_report, result) = level.verify_file(task, &path);
+        report.push(task_report);
+        if result.is_ok() {
+            outputs.push((name, path));
+        }
+    }
+
+    if !report.status.is_passed() {
+        let run = RunReport { levels: vec![report], ..RunReport::new() };
+        return Err(eyre!("Refusing to export {} since not every task passed verification", level.name))
+            .section(Table(&run).to_string());
+    }
+
+    let manifest = Manifest {
+        level: level.number,
+        name: level.name,
+        commit: git(&["rev-parse", "HEAD"]),
+        dirty: git(&["status", "--porcelain"]).is_some_and(|status| !status.is_empty()),
+        created_at: RunReport::new().started_at,
+        tasks: report.tasks,
+    };
+    if manifest.commit.is_none() {
+        tracing::warn!("Cannot determine the git commit of the sources");
+    }
+
+    let archive = archive.unwrap_or_else(|| Path::new(OUTPUT_DIR).join(format!("{}_submission.zip", level.name)));
+    let file = std::fs::File::create(&archive).wrap_err(format!("Cannot create '{}'", archive.display()))?;
+    let mut zip = ZipWriter::new(file);
+
+    for (name, path) in outputs {
+        let content = std::fs::read(&path).wrap_err(format!("Cannot read '{}'", path.display()))?;
+        add_file(&mut zip, &format!("{}_{name}.out", level.name), &content)?;
+    }
+    add_file(&mut zip, "source.zip", &source_archive()?)?;
+    let manifest = serde_json::to_vec_pretty(&manifest).wrap_err("Cannot serialize manifest")?;
+    add_file(&mut zip, "manifest.json", &manifest)?;
+
+    zip.finish().wrap_err(format!("Cannot finish '{}'", archive.display()))?;
+    tracing::info!("Exported {} to {}", level.name, archive.display());
+
+    Ok(archive)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Read;
+
+    use zip::ZipArchive;
+
+    use super::export;
+    use crate::levels::{self, Settings};
+    use crate::report::TaskReport;
+
+    #[test]
+    pub fn test_export() {
+        let dir = std::env::temp_dir().join(format!("ccc-export-test-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let level = levels::find(1).unwrap();
+        for (task, input) in level.tasks.iter().zip(level.inputs.tasks) {
+            let output = (level.solve)(input, &Settings::default(), &mut TaskReport::new(1)).unwrap();
+            std::fs::write(level.output_path_in(&dir, task), output).unwrap();
+        }
+
+        let archive = export(level, Some(dir.clone()), Some(dir.join("submission.zip"))).unwrap();
+        let mut zip = ZipArchive::new(std::fs::File::open(&archive).unwrap()).unwrap();
+        let names = zip.file_names().collect::<Vec<_>>();
+        assert!(names.contains(&"level1_5.out") && names.contains(&"source.zip"), "{names:?}");
+        let mut manifest = String::new();
+        zip.by_name("manifest.json").unwrap().read_to_string(&mut manifest).unwrap();
+        let manifest = serde_json::from_str::<serde_json::Value>(&manifest).unwrap();
+        assert_eq!(manifest["tasks"].as_array().unwrap().len(), level.tasks.len());
+        std::fs::remove_file(archive).unwrap();
+
+        std::fs::write(level.output_path_in(&dir, level.tasks[2]), "0\n").unwrap();
+        let error = export(level, Some(dir.clone()), Some(dir.join("submission.zip"))).unwrap_err();
+        assert_eq!(error.to_string(), "Refusing to export level1 since not every task passed verification");
+        assert!(!dir.join("submission.zip").exists());
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
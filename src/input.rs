@@ -1,3 +1,5 @@
+//! Parsing CCC input files into the subtasks of a level
+
 use std::fmt::Debug;
 use std::str::{FromStr, Lines};
 use eyre::{eyre, Context};
@@ -0,0 +1,240 @@
+//! Rooms that have to hold a given number of desks of several shapes
+//!
+//! An input lists the number of rooms, then every room as a `width height [rules...]` line
+//! followed by its [Inventory] line, e.g. `3x1=3 2x1=5`. [solve_input] places and verifies the
+//! desks of every room, as the `inventory` command does for a file.
+
+use std::fmt::{Display, Formatter};
+use std::str::{FromStr, Lines};
+
+use eyre::{eyre, WrapErr};
+use itertools::Itertools;
+
+use crate::budget::{Budget, Meter};
+use crate::exact;
+use crate::input::{CountedInput, FromLines, Input, Subtask};
+use crate::layout::{Desk, Layout};
+use crate::levels::level5;
+use crate::output::{Solution, Verification};
+use crate::room::{DeskShape, Rules};
+
+/// How many desks of each shape a room has to hold
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Inventory {
+    desks: Vec<(DeskShape, usize)>,
+}
+
+impl Inventory {
+    pub fn new(desks: impl IntoIterator<Item = (DeskShape, usize)>) -> Self {
+        let mut inventory = Inventory { desks: Vec::new() };
+        for (shape, count) in desks {
+            match inventory.index_of(shape.length, shape.thickness) {
+                Some(index) => inventory.desks[index].1 += count,
+                None => inventory.desks.push((shape, count)),
+            }
+        }
+        inventory
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (DeskShape, usize)> + '_ {
+        self.desks.iter().copied()
+    }
+
+    pub fn total(&self) -> usize {
+        self.desks.iter().map(|(_, count)| count).sum()
+    }
+
+    /// The position of the shape a `width` x `height` desk has, in either orientation
+    fn index_of(&self, width: usize, height: usize) -> Option<usize> {
+        self.desks.iter().position(|(shape, _)| shape.fits(width, height))
+    }
+
+    /// Counts the desks of every shape of the inventory, failing on desks of other shapes
+    pub fn classify(&self, desks: &[Desk]) -> eyre::Result<Vec<usize>> {
+        let mut counts = vec![0; self.desks.len()];
+        for desk in desks {
+            let index = self.index_of(desk.width, desk.height).ok_or(eyre!(
+                "Desk at {},{} is {}x{} which is not in the inventory {self}",
+                desk.x, desk.y, desk.width, desk.height,
+            ))?;
+            counts[index] += 1;
+        }
+        Ok(counts)
+    }
+}
+
+impl FromStr for Inventory {
+    type Err = eyre::Report;
+
+    /// Parses `shape=count` pairs separated by whitespace, e.g. `3x1=3 2x1=5`
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let desks = s.split_whitespace()
+            .map(|entry| {
+                let (shape, count) = entry.split_once('=').ok_or(eyre!("Inventory entry {entry} is not of the form shape=count"))?;
+                Ok((shape.parse()?, count.parse().wrap_err("Invalid desk count")?))
+            })
+            .collect::<eyre::Result<Vec<_>>>()?;
+
+        if desks.is_empty() {
+            return Err(eyre!("The inventory is empty"));
+        }
+
+        Ok(Inventory::new(desks))
+    }
+}
+
+impl Display for Inventory {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.desks.iter().map(|(shape, count)| format!("{shape}={count}")).join(" "))
+    }
+}
+
+/// A room given by a `width height [rules...]` line followed by its [Inventory] line
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct StockedRoom {
+    pub width: usize,
+    pub height: usize,
+    pub rules: Rules,
+    pub inventory: Inventory,
+}
+
+impl Subtask for StockedRoom {}
+
+impl FromLines for StockedRoom {
+    fn from_lines(lines: &mut Lines<'_>) -> Result<Self, eyre::Report> {
+        let line = lines.next().ok_or(eyre!("No line available to parse"))?;
+        let mut values = line.split(" ");
+        let width = values.next().ok_or(eyre!("Missing width"))?;
+        let height = values.next().ok_or(eyre!("Missing height"))?;
+
+        let mut rules = Rules::default();
+        for keyword in values {
+            rules.enable(keyword)?;
+        }
+
+        let inventory = lines.next().ok_or(eyre!("Missing inventory line"))?;
+
+        Ok(StockedRoom {
+            width: width.parse().wrap_err("Invalid width")?,
+            height: height.parse().wrap_err("Invalid height")?,
+            rules,
+            inventory: inventory.parse().wrap_err("Invalid inventory")?,
+        })
+    }
+}
+
+pub type StockedInput = CountedInput<StockedRoom>;
+
+impl StockedRoom {
+    fn layout(&self) -> Layout {
+        Layout::new(self.width, self.height).under(self.rules)
+    }
+}
+
+/// Places the largest desks first, each shape greedily up to its count
+fn greedy(room: &StockedRoom) -> Layout {
+    let mut layout = room.layout();
+
+    for (shape, count) in room.inventory.iter().sorted_by_key(|(shape, _)| std::cmp::Reverse(shape.length * shape.thickness)) {
+        let placed = layout.len();
+        layout.fill_greedy(shape);
+        layout.truncate(placed + count);
+    }
+
+    layout
+}
+
+/// Finds a layout holding the whole inventory, searching small rooms exhaustively within the
+/// budget if placing the desks greedily falls short
+pub fn place(room: &StockedRoom, meter: &Meter) -> Option<(&'static str, Layout)> {
+    let layout = greedy(room);
+    if layout.len() == room.inventory.total() {
+        return Some(("greedy", layout));
+    }
+
+    if room.width * room.height <= exact::CELL_LIMIT {
+        let _span = tracing::debug_span!("strategy", name = "exact").entered();
+        let desks = room.inventory.iter().collect::<Vec<_>>();
+        return exact::place_all(&room.layout(), &desks, meter).map(|layout| ("exact", layout));
+    }
+
+    None
+}
+
+pub fn solve(room: &StockedRoom, meter: &Meter) -> eyre::Result<Solution> {
+    let (strategy, layout) = place(room, meter).ok_or(eyre!(
+        "Could not place the inventory {} in the {}x{} room",
+        room.inventory, room.width, room.height,
+    ))?;
+
+    Ok(Solution::with_strategy(layout.render(), strategy))
+}
+
+/// Verifies that the placed desks keep the rules and match the inventory shape by shape
+pub fn verify(room: &StockedRoom, output: &str) -> eyre::Result<Verification> {
+    let layout = Layout::parse_rendered(room.width, room.height, output)?.under(room.rules);
+    layout.check_spacing()?;
+    layout.check_chairs()?;
+
+    let counts = room.inventory.classify(&layout.desks)?;
+    for ((shape, required), placed) in room.inventory.iter().zip(counts) {
+        if placed != required {
+            return Err(eyre!("Placed {placed} desks of {shape} but the inventory holds {required}"));
+        }
+    }
+
+    Ok(Verification::required(layout.len(), room.inventory.total()))
+}
+
+/// Places and verifies the desks of every room of an input, the budget applying to each room
+///
+/// The layouts are separated by blank lines like the outputs of level5.
+pub fn solve_input(input: &str, budget: Budget) -> eyre::Result<String> {
+    let input = input.parse::<StockedInput>().wrap_err("Invalid rooms")?;
+
+    let mut outputs = Vec::new();
+    for (n, room) in input.subtasks().enumerate() {
+        let _span = tracing::info_span!("subtask", subtask = n + 1).entered();
+        let solution = solve(room, &budget.start()).wrap_err(format!("Failed to place room {}", n + 1))?;
+        let verification = verify(room, &solution.output).wrap_err(format!("Room {} failed verification", n + 1))?;
+        tracing::info!(desks = verification.placed, strategy = solution.strategy, "Placed the inventory");
+        outputs.push(solution.output);
+    }
+
+    Ok(level5::reduce(outputs))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{place, solve, solve_input, verify, StockedInput};
+    use crate::budget::{Budget, Meter};
+    use crate::input::Input;
+
+    #[test]
+    pub fn test_mixed_inventory() {
+        let input = "3\n9 5\n3x1=3 2x1=5\n3 4\n3x1=1 2x1=2\n5 1\n3x1=1 2x1=1\n";
+        let input = input.parse::<StockedInput>().unwrap();
+        let [office, corner, corridor] = input.subtasks().collect::<Vec<_>>()[..] else { panic!() };
+
+        for room in [office, corner] {
+            let solution = solve(room, &Meter::default()).unwrap();
+            verify(room, &solution.output).unwrap();
+        }
+        assert_eq!(place(corner, &Meter::default()).unwrap().0, "exact");
+        assert!(solve(corridor, &Meter::default()).is_err());
+
+        verify(corner, "XXX\n...\nX.X\nX.X\n").unwrap();
+        assert!(verify(corner, "XX.\n...\nX.X\nX.X\n").is_err());
+        assert!(verify(corner, "XXX\n...\nX.X\n..X\n").is_err());
+    }
+
+    #[test]
+    pub fn test_solve_input() {
+        let output = solve_input("2\n3 4\n3x1=1 2x1=2\n4 2 chebyshev:0\n2x1=4\n", Budget::default()).unwrap();
+        let rooms = crate::layout::split_rooms(&output).collect::<Vec<_>>();
+        assert_eq!(rooms.len(), 2);
+        assert_eq!(rooms[1].lines().collect::<Vec<_>>(), ["1 1 2 2", "3 3 4 4"]);
+
+        assert!(solve_input("1\n5 1\n3x1=1 2x1=1\n", Budget::default()).is_err());
+    }
+}
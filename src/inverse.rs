@@ -0,0 +1,154 @@
+//! Inverse planning: the smallest room that fits a required number of desks
+//!
+//! Candidate rooms are tried from the smallest up. The bounds rule most of them out cheaply,
+//! the level5 placement fills the first one it can, and the placement formula decides the
+//! rooms in between. Rooms that neither succeeds for are skipped and reported, as a smaller
+//! room might still fit the desks.
+
+use clap::ValueEnum;
+
+use crate::bound;
+use crate::budget::Budget;
+use crate::layout::Layout;
+use crate::levels::level5;
+use crate::room::{DeskShape, FloorPlan, Rules};
+use crate::sat;
+
+/// What makes a room the smallest
+#[derive(Copy, Clone, Debug, Eq, PartialEq, ValueEnum)]
+pub enum Objective {
+    /// The least cells, preferring the ratio among rooms of the same area
+    Area,
+    /// The least cells among the rooms with the ratio
+    Aspect,
+}
+
+pub struct Request {
+    pub desks: usize,
+    pub desk: DeskShape,
+    pub rules: Rules,
+    pub objective: Objective,
+    /// The preferred ratio of the width to the height
+    pub ratio: f64,
+}
+
+/// The room found for a request and the desks placed in it
+pub struct Room {
+    pub width: usize,
+    pub height: usize,
+    pub strategy: &'static str,
+    pub layout: Layout,
+    /// Smaller rooms that the bounds do not rule out but the solvers could not fill within the
+    /// budget
+    pub undecided: Vec<(usize, usize)>,
+}
+
+enum Decision {
+    RuledOut,
+    Fits(&'static str, Layout),
+    Undecided,
+}
+
+impl Request {
+    /// How far the ratio of a room is from the preferred one, on a logarithmic scale so that
+    /// a room and its transposition are as far off for a square ratio
+    fn deviation(&self, width: usize, height: usize) -> f64 {
+        ((width as f64 / height as f64).ln() - self.ratio.ln()).abs()
+    }
+
+    /// The rooms that fit at least one desk, in the order of the objective
+    fn candidates(&self) -> Box<dyn Iterator<Item = (usize, usize)> + '_> {
+        let fits = |(width, height): &(usize, usize)| {
+            self.desk.orientations().into_iter().any(|(w, h)| w <= *width && h <= *height)
+        };
+
+        match self.objective {
+            Objective::Area => Box::new((self.desks * self.desk.length * self.desk.thickness..).flat_map(move |area| {
+                let mut rooms = (1..=area).filter(|width| area % width == 0).map(|width| (width, area / width)).collect::<Vec<_>>();
+                rooms.sort_by(|a, b| self.deviation(a.0, a.1).total_cmp(&self.deviation(b.0, b.1)));
+                rooms.into_iter().filter(fits)
+            })),
+            Objective::Aspect => Box::new((1..)
+                .map(|width| (width, ((width as f64 / self.ratio).round() as usize).max(1)))
+                .filter(fits)),
+        }
+    }
+
+    /// Tries candidate rooms until one fits the desks, spending the budget on each
+    pub fn solve(&self, budget: Budget) -> eyre::Result<Room> {
+        if self.desks == 0 {
+            return Err(eyre::eyre!("Every room fits no desks"));
+        }
+        if !(self.ratio.is_finite() && self.ratio > 0.0) {
+            return Err(eyre::eyre!("The ratio {} is not a positive number", self.ratio));
+        }
+
+        let mut undecided = Vec::new();
+        for (width, height) in self.candidates() {
+            match self.decide(width, height, budget) {
+                Decision::RuledOut => {},
+                Decision::Fits(strategy, layout) => return Ok(Room { width, height, strategy, layout, undecided }),
+                Decision::Undecided => {
+                    tracing::debug!(width, height, "Skipping undecided room");
+                    undecided.push((width, height));
+                }
+            }
+        }
+
+        unreachable!("Long enough rooms fit any number of desks")
+    }
+
+    fn decide(&self, width: usize, height: usize, budget: Budget) -> Decision {
+        let plan = FloorPlan { rules: self.rules, ..FloorPlan::open(width, height, self.desks, self.desk) };
+        let spacing = self.rules.spacing;
+        let meter = budget.start();
+        if bound::counting(width * height, width, height, self.desk, spacing) < self.desks
+            || bound::maximum(&plan.floor, self.desk, spacing, &meter.sibling()).desks < self.desks
+        {
+            return Decision::RuledOut;
+        }
+
+        let _span = tracing::debug_span!("room", width, height).entered();
+        let mut placement = level5::place_desks(&plan, &meter);
+        if placement.layout.len() >= self.desks {
+            placement.layout.truncate(self.desks);
+            return Decision::Fits(placement.strategy, placement.layout);
+        }
+
+        if width * height > sat::CELL_LIMIT || meter.is_exhausted() {
+            return Decision::Undecided;
+        }
+        let room = Layout::with_floor(&plan.floor).under(self.rules);
+        match sat::place(&room, self.desk, self.desks, &meter) {
+            Some(layout) => Decision::Fits("sat", layout),
+            None if meter.is_exhausted() => Decision::Undecided,
+            None => Decision::RuledOut,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Objective, Request};
+    use crate::budget::Budget;
+    use crate::room::{DeskShape, Rules};
+
+    fn solve(desks: usize, room_line: &str, objective: Objective, ratio: f64) -> (usize, usize) {
+        let (desk, rules) = Rules::parse_columns(room_line.split_whitespace(), DeskShape::new(2, 1)).unwrap();
+        let room = Request { desks, desk, rules, objective, ratio }.solve(Budget::default()).unwrap();
+        assert!(room.undecided.is_empty());
+
+        room.layout.check_shape(desk).unwrap();
+        room.layout.check_spacing().unwrap();
+        assert_eq!(room.layout.len(), desks);
+        (room.width, room.height)
+    }
+
+    #[test]
+    pub fn test_smallest_rooms() {
+        assert_eq!(solve(4, "", Objective::Area, 1.0), (1, 11));
+        assert_eq!(solve(5, "3x1 chebyshev:0", Objective::Area, 1.0), (3, 5));
+        assert_eq!(solve(6, "", Objective::Aspect, 2.0), (7, 4));
+        assert_eq!(solve(6, "", Objective::Aspect, 1.0), (5, 5));
+    }
+}
@@ -0,0 +1,566 @@
+//! Desks placed in a room, with the checks of every placement rule and the output formats
+
+use std::collections::BTreeMap;
+
+use eyre::{eyre, WrapErr};
+use itertools::Itertools;
+
+use crate::room::{DeskShape, Floor, Rules};
+
+/// A desk occupying the cells `x..x + width` and `y..y + height`
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub struct Desk {
+    pub x: usize,
+    pub y: usize,
+    pub width: usize,
+    pub height: usize,
+}
+
+impl Desk {
+    pub fn new(x: usize, y: usize, (width, height): (usize, usize)) -> Self {
+        Desk { x, y, width, height }
+    }
+
+    pub fn cells(&self) -> impl Iterator<Item = (usize, usize)> + '_ {
+        (self.y..self.y + self.height).flat_map(move |y| (self.x..self.x + self.width).map(move |x| (x, y)))
+    }
+
+    pub fn transposed(&self) -> Desk {
+        Desk { x: self.y, y: self.x, width: self.height, height: self.width }
+    }
+
+    /// The distances between the closest cells of two desks along both axes, 0 if they overlap
+    pub fn gaps(&self, other: &Desk) -> (usize, usize) {
+        let dx = other.x.saturating_sub(self.x + self.width - 1).max(self.x.saturating_sub(other.x + other.width - 1));
+        let dy = other.y.saturating_sub(self.y + self.height - 1).max(self.y.saturating_sub(other.y + other.height - 1));
+        (dx, dy)
+    }
+}
+
+/// Desks placed in a `width` x `height` room, optionally on a [Floor] with blocked cells and
+/// under additional [Rules]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Layout {
+    pub width: usize,
+    pub height: usize,
+    pub desks: Vec<Desk>,
+    floor: Option<Floor>,
+    rules: Rules,
+}
+
+impl Layout {
+    pub fn new(width: usize, height: usize) -> Self {
+        Layout { width, height, desks: Vec::new(), floor: None, rules: Rules::default() }
+    }
+
+    pub fn with_floor(floor: &Floor) -> Self {
+        Layout::new(floor.width, floor.height).on(floor)
+    }
+
+    /// Puts the desks onto a floor of the same size, without removing any of them
+    pub fn on(mut self, floor: &Floor) -> Self {
+        debug_assert_eq!((floor.width, floor.height), (self.width, self.height));
+        self.floor = (!floor.is_open()).then(|| floor.clone());
+        self
+    }
+
+    /// Applies the rules to the desks, without removing any of them
+    pub fn under(mut self, rules: Rules) -> Self {
+        self.rules = rules;
+        self
+    }
+
+    pub fn rules(&self) -> Rules {
+        self.rules
+    }
+
+    pub fn is_blocked(&self, x: usize, y: usize) -> bool {
+        self.floor.as_ref().is_some_and(|floor| floor.is_blocked(x, y))
+    }
+
+    fn covers_blocked(&self, desk: &Desk) -> bool {
+        self.floor.is_some() && desk.cells().any(|(x, y)| self.is_blocked(x, y))
+    }
+
+    /// Removes the desks that stand on blocked cells
+    pub fn remove_blocked(&mut self) {
+        if self.floor.is_some() {
+            let desks = std::mem::take(&mut self.desks);
+            self.desks = desks.into_iter().filter(|desk| !self.covers_blocked(desk)).collect();
+        }
+    }
+
+    /// Removes desks in order, keeping only those that leave the walkway to the entry and a chair
+    /// for every desk
+    pub fn retain_rules(&mut self) {
+        if self.is_constrained() {
+            for desk in std::mem::take(&mut self.desks) {
+                self.desks.push(desk);
+                if !self.follows_rules() {
+                    self.desks.pop();
+                }
+            }
+        }
+    }
+
+    /// Whether the rules go beyond the spacing, so that [Layout::follows_rules] has to be checked
+    pub fn is_constrained(&self) -> bool {
+        self.entry().is_some() || self.rules.chairs
+    }
+
+    /// Whether the desks keep the walkway and all get a chair, as far as the rules require
+    pub fn follows_rules(&self) -> bool {
+        self.check_walkway().is_ok() && self.check_chairs().is_ok()
+    }
+
+    fn entry(&self) -> Option<(usize, usize)> {
+        self.floor.as_ref().and_then(Floor::entry)
+    }
+
+    pub fn len(&self) -> usize {
+        self.desks.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.desks.is_empty()
+    }
+
+    /// The index of the desk covering each cell, offset by one so that 0 marks a free cell
+    pub fn grid(&self) -> Vec<usize> {
+        let mut grid = vec![0; self.width * self.height];
+        for (index, desk) in self.desks.iter().enumerate() {
+            for (x, y) in desk.cells() {
+                grid[y * self.width + x] = index + 1;
+            }
+        }
+        grid
+    }
+
+    pub fn contains(&self, desk: &Desk) -> bool {
+        desk.x + desk.width <= self.width && desk.y + desk.height <= self.height
+    }
+
+    pub fn place(&mut self, desk: Desk) {
+        debug_assert!(self.contains(&desk), "{desk:?} is outside of the {}x{} room", self.width, self.height);
+        self.desks.push(desk);
+    }
+
+    /// Keeps only the first `count` desks
+    pub fn truncate(&mut self, count: usize) {
+        self.desks.truncate(count);
+    }
+
+    pub fn transposed(&self) -> Layout {
+        Layout {
+            width: self.height,
+            height: self.width,
+            desks: self.desks.iter().map(Desk::transposed).collect(),
+            floor: self.floor.as_ref().map(Floor::transposed),
+            rules: self.rules,
+        }
+    }
+
+    /// The cells around a desk that other desks have to keep clear of under the spacing rule
+    fn surroundings(&self, desk: &Desk) -> impl Iterator<Item = (usize, usize)> + '_ {
+        let gap = self.rules.spacing.gap;
+        let x_range = desk.x.saturating_sub(gap)..(desk.x + desk.width + gap).min(self.width);
+        let y_range = desk.y.saturating_sub(gap)..(desk.y + desk.height + gap).min(self.height);
+
+        let desk = *desk;
+        y_range.cartesian_product(x_range)
+            .map(|(y, x)| (x, y))
+            .filter(move |(x, y)| {
+                let dx = desk.x.saturating_sub(*x).max(x.saturating_sub(desk.x + desk.width - 1));
+                let dy = desk.y.saturating_sub(*y).max(y.saturating_sub(desk.y + desk.height - 1));
+                !self.rules.spacing.keeps(dx, dy)
+            })
+    }
+
+    /// Whether a desk can be added to the desks on the grid without breaking the spacing rule
+    pub fn is_free(&self, grid: &[usize], desk: &Desk) -> bool {
+        self.contains(desk)
+            && !self.covers_blocked(desk)
+            && self.surroundings(desk).all(|(x, y)| grid[y * self.width + x] == 0)
+    }
+
+    /// Every position and orientation a desk of the given shape could be added at
+    pub fn candidates(&self, shape: DeskShape) -> Vec<Desk> {
+        let grid = self.grid();
+        let mut desks = Vec::new();
+        for y in 0..self.height {
+            for x in 0..self.width {
+                for size in shape.orientations() {
+                    let desk = Desk::new(x, y, size);
+                    if self.is_free(&grid, &desk) {
+                        desks.push(desk);
+                    }
+                }
+            }
+        }
+        desks
+    }
+
+    /// For every cell of the room grown by the spacing gap, the desks whose box covers it
+    ///
+    /// The box of a desk is the desk grown to the right and bottom by the gap. Under a
+    /// chebyshev spacing, desks keep the spacing exactly if their boxes do not overlap.
+    pub fn boxes(&self, desks: &[Desk]) -> Vec<Vec<usize>> {
+        let gap = self.rules.spacing.gap;
+        let width = self.width + gap;
+        let mut covering = vec![Vec::new(); width * (self.height + gap)];
+        for (index, desk) in desks.iter().enumerate() {
+            for y in desk.y..desk.y + desk.height + gap {
+                for x in desk.x..desk.x + desk.width + gap {
+                    covering[y * width + x].push(index);
+                }
+            }
+        }
+        covering
+    }
+
+    /// The pairs of desks, given in row-major order, that break the spacing rule together
+    pub fn conflicts(&self, desks: &[Desk]) -> Vec<(usize, usize)> {
+        let spacing = self.rules.spacing;
+        let reach = desks.iter().map(|desk| desk.height).max().unwrap_or_default() + spacing.gap;
+        let mut conflicts = Vec::new();
+        for (a, desk) in desks.iter().enumerate() {
+            for (b, other) in desks.iter().enumerate().skip(a + 1) {
+                if other.y > desk.y + reach {
+                    break;
+                }
+                let (dx, dy) = desk.gaps(other);
+                if !spacing.keeps(dx, dy) {
+                    conflicts.push((a, b));
+                }
+            }
+        }
+        conflicts
+    }
+
+    /// Adds desks of the given shape wherever they fit, scanning the room row by row
+    ///
+    /// Desks that would break the spacing, the walkway or leave a desk without a chair are
+    /// skipped.
+    pub fn fill_greedy(&mut self, shape: DeskShape) {
+        let mut grid = self.grid();
+
+        for y in 0..self.height {
+            for x in 0..self.width {
+                for size in shape.orientations() {
+                    let desk = Desk::new(x, y, size);
+                    if self.is_free(&grid, &desk) {
+                        self.desks.push(desk);
+                        if self.is_constrained() && !self.follows_rules() {
+                            self.desks.pop();
+                            continue;
+                        }
+                        for (x, y) in desk.cells() {
+                            grid[y * self.width + x] = self.desks.len();
+                        }
+                        break;
+                    }
+                }
+            }
+        }
+    }
+
+    /// Renders every cell as the id of the desk on it, starting at 1, or 0 for free cells
+    pub fn ids(&self) -> String {
+        self.grid()
+            .chunks(self.width.max(1))
+            .take(self.height)
+            .map(|row| row.iter().join(" "))
+            .join("\r\n")
+    }
+
+    /// Renders desk cells as `X`, blocked cells as `#` and free cells as `.`
+    pub fn marks(&self) -> String {
+        let grid = self.grid();
+
+        (0..self.height)
+            .map(|y| {
+                (0..self.width)
+                    .map(|x| match grid[y * self.width + x] {
+                        0 if self.is_blocked(x, y) => '#',
+                        0 => '.',
+                        _ => 'X',
+                    })
+                    .collect::<String>()
+            })
+            .join("\r\n")
+    }
+
+    /// Renders by [Layout::ids] if desks may touch, which [Layout::marks] could not tell apart,
+    /// and by marks otherwise
+    pub fn render(&self) -> String {
+        if self.rules.spacing.gap == 0 { self.ids() } else { self.marks() }
+    }
+
+    /// Parses the output of [Layout::render]
+    pub fn parse_rendered(width: usize, height: usize, output: &str) -> eyre::Result<Self> {
+        if output.trim_start().starts_with(|c: char| c.is_ascii_digit()) {
+            Layout::parse_ids(width, height, output)
+        } else {
+            Layout::parse_marks(width, height, output)
+        }
+    }
+
+    fn parse_rows<T>(width: usize, height: usize, output: &str, parse_row: impl Fn(&str) -> eyre::Result<Vec<T>>) -> eyre::Result<Vec<T>> {
+        let rows = output.lines().filter(|line| !line.trim().is_empty()).collect::<Vec<_>>();
+        if rows.len() != height {
+            return Err(eyre!("Output has {} rows for a {height} high room", rows.len()));
+        }
+
+        let mut cells = Vec::with_capacity(width * height);
+        for row in rows {
+            let mut row = parse_row(row)?;
+            if row.len() != width {
+                return Err(eyre!("Output contains {} cells in a row of a {width} wide room", row.len()));
+            }
+            cells.append(&mut row);
+        }
+
+        Ok(cells)
+    }
+
+    fn from_groups(width: usize, height: usize, groups: BTreeMap<usize, Vec<(usize, usize)>>) -> eyre::Result<Self> {
+        let mut layout = Layout::new(width, height);
+
+        for (id, cells) in groups {
+            let (min_x, max_x) = cells.iter().map(|(x, _)| *x).minmax().into_option().unwrap_or_default();
+            let (min_y, max_y) = cells.iter().map(|(_, y)| *y).minmax().into_option().unwrap_or_default();
+            let desk = Desk { x: min_x, y: min_y, width: max_x - min_x + 1, height: max_y - min_y + 1 };
+
+            if desk.width * desk.height != cells.len() {
+                return Err(eyre!("Desk {id} at {min_x},{min_y} does not form a rectangle"));
+            }
+            layout.desks.push(desk);
+        }
+
+        Ok(layout)
+    }
+
+    /// Parses a matrix of desk ids, where 0 marks a free cell
+    pub fn parse_ids(width: usize, height: usize, output: &str) -> eyre::Result<Self> {
+        let cells = Self::parse_rows(width, height, output, |row| {
+            row.split_whitespace()
+                .map(|id| id.parse::<usize>().wrap_err("The desk id of a cell is not numeric"))
+                .collect()
+        })?;
+
+        let mut groups = BTreeMap::<usize, Vec<_>>::new();
+        for (index, id) in cells.into_iter().enumerate() {
+            if id != 0 {
+                groups.entry(id).or_default().push((index % width, index / width));
+            }
+        }
+
+        Self::from_groups(width, height, groups)
+    }
+
+    /// Parses a matrix of `X` desk cells and `.` free cells, where each connected group of
+    /// `X` cells is one desk and `#` marks a blocked cell
+    pub fn parse_marks(width: usize, height: usize, output: &str) -> eyre::Result<Self> {
+        let cells = Self::parse_rows(width, height, output, |row| {
+            row.trim()
+                .chars()
+                .map(|cell| match cell {
+                    'X' => Ok(true),
+                    '.' | '#' => Ok(false),
+                    other => Err(eyre!("Invalid cell '{other}'")),
+                })
+                .collect()
+        })?;
+
+        let room = Layout::new(width, height);
+        let mut group_of = vec![0; cells.len()];
+        let mut groups = BTreeMap::<usize, Vec<_>>::new();
+        for start in 0..cells.len() {
+            if !cells[start] || group_of[start] != 0 {
+                continue;
+            }
+
+            let id = groups.len() + 1;
+            let mut stack = vec![start];
+            group_of[start] = id;
+            while let Some(index) = stack.pop() {
+                let (x, y) = (index % width, index / width);
+                groups.entry(id).or_default().push((x, y));
+
+                for neighbour in room.neighbours(index) {
+                    if cells[neighbour] && group_of[neighbour] == 0 {
+                        group_of[neighbour] = id;
+                        stack.push(neighbour);
+                    }
+                }
+            }
+        }
+
+        Self::from_groups(width, height, groups)
+    }
+
+    /// Checks that every desk has the given shape in one of its orientations
+    pub fn check_shape(&self, shape: DeskShape) -> eyre::Result<()> {
+        match self.desks.iter().find(|desk| !shape.fits(desk.width, desk.height)) {
+            Some(desk) => Err(eyre!(
+                "Desk at {},{} is {}x{} but desks are {shape}",
+                desk.x, desk.y, desk.width, desk.height,
+            )),
+            None => Ok(()),
+        }
+    }
+
+    /// Checks that no desk stands on a blocked cell of the floor
+    pub fn check_floor(&self) -> eyre::Result<()> {
+        match self.desks.iter().find(|desk| self.covers_blocked(desk)) {
+            Some(desk) => Err(eyre!("Desk at {},{} stands on a blocked cell", desk.x, desk.y)),
+            None => Ok(()),
+        }
+    }
+
+    /// Checks that the free cells form a walkway from the entry, if the floor has one
+    ///
+    /// Every free cell has to be 4-connected to the entry and every desk has to border at
+    /// least one free cell.
+    pub fn check_walkway(&self) -> eyre::Result<()> {
+        let Some((entry_x, entry_y)) = self.entry() else {
+            return Ok(());
+        };
+
+        let grid = self.grid();
+        let is_free = |index: usize| grid[index] == 0 && !self.is_blocked(index % self.width, index / self.width);
+        let entry = entry_y * self.width + entry_x;
+        if !is_free(entry) {
+            return Err(eyre!("The entry at {entry_x},{entry_y} is not free"));
+        }
+
+        let mut reached = vec![false; grid.len()];
+        let mut stack = vec![entry];
+        reached[entry] = true;
+        while let Some(index) = stack.pop() {
+            for neighbour in self.neighbours(index) {
+                if is_free(neighbour) && !reached[neighbour] {
+                    reached[neighbour] = true;
+                    stack.push(neighbour);
+                }
+            }
+        }
+
+        if let Some(index) = (0..grid.len()).find(|index| is_free(*index) && !reached[*index]) {
+            return Err(eyre!("Free cell at {},{} is cut off from the entry", index % self.width, index / self.width));
+        }
+
+        let stranded = self.desks.iter().find(|desk| {
+            !desk.cells().any(|(x, y)| self.neighbours(y * self.width + x).any(|neighbour| reached[neighbour]))
+        });
+        match stranded {
+            Some(desk) => Err(eyre!("Desk at {},{} does not border the walkway", desk.x, desk.y)),
+            None => Ok(()),
+        }
+    }
+
+    /// Assigns every desk its own free cell next to one of its edges, as far as possible
+    ///
+    /// Finds a maximum bipartite matching between desks and free cells with augmenting paths.
+    pub fn chairs(&self) -> Vec<Option<(usize, usize)>> {
+        let grid = self.grid();
+        let candidates = self.desks.iter()
+            .map(|desk| {
+                desk.cells()
+                    .flat_map(|(x, y)| self.neighbours(y * self.width + x))
+                    .filter(|index| grid[*index] == 0 && !self.is_blocked(index % self.width, index / self.width))
+                    .unique()
+                    .collect::<Vec<_>>()
+            })
+            .collect::<Vec<_>>();
+
+        fn assign(desk: usize, candidates: &[Vec<usize>], visited: &mut [bool], seated: &mut [Option<usize>]) -> bool {
+            for &cell in &candidates[desk] {
+                if visited[cell] {
+                    continue;
+                }
+                visited[cell] = true;
+                if seated[cell].is_none_or(|other| assign(other, candidates, visited, seated)) {
+                    seated[cell] = Some(desk);
+                    return true;
+                }
+            }
+            false
+        }
+
+        let mut seated = vec![None; grid.len()];
+        for desk in 0..self.desks.len() {
+            assign(desk, &candidates, &mut vec![false; grid.len()], &mut seated);
+        }
+
+        let mut chairs = vec![None; self.desks.len()];
+        for (index, desk) in seated.into_iter().enumerate() {
+            if let Some(desk) = desk {
+                chairs[desk] = Some((index % self.width, index / self.width));
+            }
+        }
+        chairs
+    }
+
+    /// Checks that every desk gets its own chair cell, if the rules require chairs
+    pub fn check_chairs(&self) -> eyre::Result<()> {
+        if !self.rules.chairs {
+            return Ok(());
+        }
+
+        let seatless = self.chairs().into_iter()
+            .zip(&self.desks)
+            .filter(|(chair, _)| chair.is_none())
+            .map(|(_, desk)| format!("{},{}", desk.x, desk.y))
+            .collect::<Vec<_>>();
+        if seatless.is_empty() {
+            Ok(())
+        } else {
+            Err(eyre!("No chair left for the desks at {}", seatless.join("; ")))
+        }
+    }
+
+    /// The indices of the cells sharing an edge with the cell at `index`
+    fn neighbours(&self, index: usize) -> impl Iterator<Item = usize> {
+        let (x, y, width) = (index % self.width, index / self.width, self.width);
+        [
+            (x > 0).then(|| index - 1),
+            (x + 1 < width).then(|| index + 1),
+            (y > 0).then(|| index - width),
+            (y + 1 < self.height).then(|| index + width),
+        ]
+        .into_iter()
+        .flatten()
+    }
+
+    /// Checks that all desks keep the spacing rule to each other
+    pub fn check_spacing(&self) -> eyre::Result<()> {
+        let grid = self.grid();
+
+        for (index, desk) in self.desks.iter().enumerate() {
+            let close = self.surroundings(desk)
+                .map(|(x, y)| grid[y * self.width + x])
+                .find(|other| *other != 0 && *other != index + 1);
+            if let Some(other) = close {
+                let other = self.desks[other - 1];
+                return Err(eyre!(
+                    "Desk at {},{} is closer than the spacing {} to desk at {},{}",
+                    desk.x, desk.y, self.rules.spacing, other.x, other.y,
+                ));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Splits outputs of several rooms that are separated by blank lines
+pub fn split_rooms(output: &str) -> impl Iterator<Item = String> + '_ {
+    output.lines()
+        .collect::<Vec<_>>()
+        .split(|line| line.trim().is_empty())
+        .filter(|room| !room.is_empty())
+        .map(|room| room.join("\n"))
+        .collect::<Vec<_>>()
+        .into_iter()
+}
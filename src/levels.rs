@@ -1,6 +1,20 @@
+//! The levels of the contest and the pipeline that solves, verifies and writes their tasks
+
+use std::borrow::Cow;
+use std::path::{Path, PathBuf};
+use std::time::Instant;
+
+use crate::budget::Budget;
+use crate::cache::Cache;
 use crate::input::{Input, Inputs};
+use crate::output::Solution;
+use crate::report::{messages, millis, LevelReport, RunReport, Status, SubtaskReport, TaskReport};
 use eyre::{WrapErr, Report};
 
+pub const INPUT_DIR: &str = concat!(::location_macros::workspace_dir!(), "/inputs/");
+pub const OUTPUT_DIR: &str = concat!(::location_macros::workspace_dir!(), "/out/");
+pub const CACHE_DIR: &str = concat!(::location_macros::workspace_dir!(), "/out/.cache/");
+
 macro_rules! include_tasks {
     ($path:expr => [$($task_name:expr),*]) => {
         include_tasks!($path => [$($task_name,)*])
@@ -40,13 +54,13 @@ macro_rules! count {
 
 #[cfg(test)]
 macro_rules! tests {
-    ($level:ident($name:literal, $number:literal, [])) => {
-        tests!($level($name, $number, ["1", "2", "3", "4", "5"]));
+    ($(#[$meta:meta])* $level:ident($name:literal, $number:literal, [])) => {
+        tests!($(#[$meta])* $level($name, $number, ["1", "2", "3", "4", "5"]));
     };
-    ($level:ident($name:literal, $number:literal, [$($task:literal),*])) => {
-        tests!($level($name, $number, [$($task,)*]));
+    ($(#[$meta:meta])* $level:ident($name:literal, $number:literal, [$($task:literal),*])) => {
+        tests!($(#[$meta])* $level($name, $number, [$($task,)*]));
     };
-    ($level:ident($name:literal, $number:literal, [$($task:literal,)*])) => {
+    ($(#[$meta:meta])* $level:ident($name:literal, $number:literal, [$($task:literal,)*])) => {
         pub mod $level {
             use super::*;
             const TASKS: [&str; count!($($task)*)] = [$($task,)*];
@@ -59,10 +73,10 @@ macro_rules! tests {
                     .unwrap();
                 let io_pairs = ::std::iter::zip(
                     input.subtasks(),
-                    crate::levels::$level::split_example(example_out)
+                    crate::levels::$level::split_output(example_out)
                 );
                 for (input, output) in io_pairs {
-                    crate::levels::$level::verify(input, output)
+                    crate::levels::$level::verify(input, &output)
                         .wrap_err(::eyre::eyre!("Failed to verify example for input {input:?}"))
                         .unwrap();
                 }
@@ -75,94 +89,175 @@ macro_rules! tests {
                 let _ = $level(raw.example_in).unwrap();
             }
 
-            $(
-                ::concat_idents::concat_idents!(fn_name = test_, $level, _, $task {
-                    #[test]
-                    pub fn fn_name() {
-                        let task = TASKS.iter().position(|task| *task == $task).unwrap();
-                        let raw_input = RAW_INPUTS[$number - 1].tasks[task];
-
-                        $level(raw_input).unwrap();
-                    }
-                });
-            )*
+            task_tests!([$(#[$meta])*] $level($number, [$($task,)*]));
         }
     };
 }
 
+/// Generates one test per task, carrying the attributes given to the level
+#[cfg(test)]
+macro_rules! task_tests {
+    ([$(#[$meta:meta])*] $level:ident($number:literal, [])) => {};
+    ([$(#[$meta:meta])*] $level:ident($number:literal, [$task:literal, $($rest:literal,)*])) => {
+        ::concat_idents::concat_idents!(fn_name = test_, $level, _, $task {
+            #[test]
+            $(#[$meta])*
+            pub fn fn_name() {
+                let task = TASKS.iter().position(|task| *task == $task).unwrap();
+                let raw_input = RAW_INPUTS[$number - 1].tasks[task];
+
+                $level(raw_input).unwrap();
+            }
+        });
+
+        task_tests!([$(#[$meta])*] $level($number, [$($rest,)*]));
+    };
+}
+
+macro_rules! task_names {
+    ([]) => {
+        task_names!(["1", "2", "3", "4", "5"])
+    };
+    ([$($task:literal),* $(,)?]) => {
+        &[$($task,)*]
+    };
+}
+
+/// Wires levels into the pipeline
+///
+/// Attributes in front of a level, e.g. `#[ignore = "reason"]`, are applied to its task tests.
 macro_rules! levels {
-    ($($level:ident($name:literal, $number:literal, [$($task:literal,)*]))*) => {
+    ($($(#[$meta:meta])* $level:ident($name:literal, $number:literal, [$($task:literal,)*]))*) => {
         $(
             #[cfg(feature = $name)]
             pub mod $level;
 
             #[cfg(feature = $name)]
-            pub fn $level(input: &'static str) -> Result<String, Vec<::eyre::Report>> {
-                let input: $level::Input = input.parse().map_err(|err| vec![err])?;
-
-                let mut results = Vec::new();
-                let mut errors = Vec::new();
-
-                for input in input.subtasks() {
-                    let result = $level::map(input)
-                        .wrap_err("Failed to map input to output")
-                        .and_then(|result| {
-                            $level::verify(&input, &result)
-                                .map(|_| result)
-                        })
-                        .wrap_err("Verification failed");
-                    match result {
-                        Ok(result) => results.push(result),
-                        Err(error) => errors.push(error.wrap_err(format!("Subtask {input:?} has failed"))),
+            #[allow(dead_code)]
+            pub fn $level(input: &str) -> Result<String, Vec<::eyre::Report>> {
+                ::concat_idents::concat_idents!(fn_name = solve_, $level {
+                    fn_name(input, &Settings::default(), &mut TaskReport::new(0))
+                })
+            }
+
+            #[cfg(feature = $name)]
+            ::concat_idents::concat_idents!(fn_name = solve_, $level {
+                pub fn fn_name(input: &str, settings: &Settings, report: &mut TaskReport) -> Result<String, Vec<::eyre::Report>> {
+                    let input: $level::Input = input.parse()
+                        .map_err(|error| report.fail(Status::ParseFailed, (error as Report).wrap_err("Failed to parse input")))
+                        .map_err(|error| vec![error])?;
+
+                    let mut results = Vec::new();
+                    let mut errors = Vec::new();
+
+                    for (n, input) in input.subtasks().enumerate() {
+                        let span = ::tracing::info_span!("subtask", subtask = n + 1, input = ?input, strategy = ::tracing::field::Empty);
+                        let _entered = span.enter();
+                        let start = Instant::now();
+                        let mut subtask = SubtaskReport::new(n + 1, input);
+
+                        let cached = settings.cache.and_then(|cache| cache.get($name, input, |output| $level::verify(input, output).is_ok()));
+                        let mut cut_short = None;
+                        let mapped = match cached {
+                            Some(output) => Ok(Solution::with_strategy(output, "cache")),
+                            None => {
+                                let meter = settings.budget.start();
+                                let mapped = ::tracing::debug_span!("map").in_scope(|| $level::map(input, &meter));
+                                cut_short = meter.is_exhausted().then(|| meter.spent());
+                                mapped.map(Solution::from)
+                            },
+                        };
+                        let result = mapped
+                            .wrap_err("Failed to map input to output")
+                            .map_err(|error| (Status::SolveFailed, error))
+                            .and_then(|solution| {
+                                subtask.strategy = solution.strategy;
+                                subtask.improvement = solution.improvement;
+                                subtask.bound = solution.bound;
+                                if let Some(improvement) = solution.improvement {
+                                    ::tracing::info!(from = improvement.from, start = improvement.start, end = improvement.end, "Local search added desks");
+                                }
+                                if let Some(strategy) = solution.strategy {
+                                    span.record("strategy", strategy);
+                                }
+                                ::tracing::debug_span!("verify").in_scope(|| $level::verify(input, &solution.output))
+                                    .wrap_err("Verification failed")
+                                    .map_err(|error| (Status::VerifyFailed, error))
+                                    .map(|verification| {
+                                        ::tracing::debug!(placed = verification.placed, required = ?verification.required, "Verified subtask");
+                                        if let Some(nodes) = cut_short {
+                                            subtask.cut_short(nodes, verification.placed, solution.bound);
+                                        }
+                                        subtask.desks = Some(verification.into());
+                                        if let Some(cache) = settings.cache.filter(|_| solution.strategy != Some("cache")) {
+                                            if let Err(error) = cache.put($name, input, &solution.output) {
+                                                ::tracing::warn!("{error:#}");
+                                            }
+                                        }
+                                        solution.output
+                                    })
+                            });
+                        subtask.duration_ms = millis(start.elapsed());
+
+                        match result {
+                            Ok(result) => results.push(result),
+                            Err((status, error)) => {
+                                ::tracing::warn!(?status, "{error:#}");
+                                let error = error.wrap_err(format!("Subtask {input:?} has failed"));
+                                subtask.status = status;
+                                subtask.messages = messages(&error);
+                                errors.push(error);
+                            },
+                        }
+                        report.push(subtask);
                     }
-                }
 
-                if errors.len() == 0 {
-                    Ok($level::reduce(results))
-                } else {
-                    Err(errors)
+                    if errors.is_empty() {
+                        Ok($level::reduce(results))
+                    } else {
+                        Err(errors)
+                    }
                 }
-            }
+            });
 
             #[cfg(feature = $name)]
-            ::concat_idents::concat_idents!(fn_name = run_, $level {
-                pub fn fn_name() -> Result<(), Vec<::eyre::Report>> {
-                    let tasks = &RAW_INPUTS[$number - 1].tasks;
+            ::concat_idents::concat_idents!(fn_name = verify_, $level {
+                pub fn fn_name(input: &str, output: &str, report: &mut TaskReport) -> Result<(), Vec<::eyre::Report>> {
+                    let input: $level::Input = input.parse()
+                        .map_err(|error| report.fail(Status::ParseFailed, (error as Report).wrap_err("Failed to parse input")))
+                        .map_err(|error| vec![error])?;
 
-                    let output_dir = concat!(::location_macros::workspace_dir!(), "/out/", $name, "/");
-                    std::fs::create_dir_all(output_dir)
-                        .wrap_err("Cannot crate '{output_dir}' directory")
-                        .map_err(|err| vec![err])?;
+                    let outputs = $level::split_output(output).collect::<Vec<_>>();
+                    let subtask_count = input.subtasks().count();
+                    if outputs.len() != subtask_count {
+                        let error = ::eyre::eyre!("Output contains {} results for {subtask_count} subtasks", outputs.len());
+                        return Err(vec![report.fail(Status::VerifyFailed, error)]);
+                    }
 
                     let mut errors = Vec::new();
-                    for (n, raw_input) in tasks.iter().enumerate() {
-                        let result = $level(raw_input)
-                            .map_err(|errors| {
-                                errors.into_iter()
-                                    .map(|error| (error as Report).wrap_err(format!("Failed to run task {}", n + 1)))
-                                    .collect()
-                            });
 
-                        let result = result.and_then(|output| {
-                            let out = format!(concat!(::location_macros::workspace_dir!(), "/out/", $name, "/", $name, "_{}.out"), n + 1);
-                            ::tracing::info!("Writing {out}");
-                            std::fs::write(&out, output)
-                                .wrap_err(format!("Cannot write to '{out}'"))
-                                .map_err(|err| vec![err])
-                        });
+                    for (n, (input, output)) in ::std::iter::zip(input.subtasks(), outputs).enumerate() {
+                        let _span = ::tracing::info_span!("subtask", subtask = n + 1, input = ?input).entered();
+                        let start = Instant::now();
+                        let mut subtask = SubtaskReport::new(n + 1, input);
 
-                        if let Err(mut e) = result {
-                            errors.append(&mut e);
+                        match $level::verify(input, &output) {
+                            Ok(verification) => subtask.desks = Some(verification.into()),
+                            Err(error) => {
+                                let error = error.wrap_err(format!("Subtask {input:?} has failed verification"));
+                                subtask.status = Status::VerifyFailed;
+                                subtask.messages = messages(&error);
+                                errors.push(error);
+                            },
                         }
+                        subtask.duration_ms = millis(start.elapsed());
+                        report.push(subtask);
                     }
 
-                    match errors.len() {
-                        0 => Ok(()),
-                        _ => Err(
-                            errors.into_iter()
-                                .map(|error| error.wrap_err(concat!("Failed to run ", $name)))
-                                .collect()
-                        ),
+                    if errors.is_empty() {
+                        Ok(())
+                    } else {
+                        Err(errors)
                     }
                 }
             });
@@ -175,23 +270,188 @@ macro_rules! levels {
             )*
         ];
 
+        pub const LEVELS: &[Level] = &[
+            $(
+                #[cfg(feature = $name)]
+                Level {
+                    number: $number,
+                    name: $name,
+                    tasks: task_names!([$($task,)*]),
+                    inputs: &RAW_INPUTS[$number - 1],
+                    solve: ::concat_idents::concat_idents!(fn_name = solve_, $level { fn_name }),
+                    verify: ::concat_idents::concat_idents!(fn_name = verify_, $level { fn_name }),
+                },
+            )*
+        ];
+
         #[cfg(test)]
         pub mod test {
             #[allow(unused_imports)]
             use super::*;
 
             $(
-                tests!($level($name, $number, [$($task,)*]));
+                tests!($(#[$meta])* $level($name, $number, [$($task,)*]));
             )*
         }
     };
-    ($($level:ident($name:literal, $number:literal, [$($task:literal),*]))*) => {
-        levels!($($level($name, $number, [$($task,)*]))*);
+    ($($(#[$meta:meta])* $level:ident($name:literal, $number:literal, [$($task:literal),*]))*) => {
+        levels!($($(#[$meta])* $level($name, $number, [$($task,)*]))*);
     };
 }
 
+/// Looks up a level that is enabled in this build by its number
+pub fn find(number: usize) -> eyre::Result<&'static Level> {
+    LEVELS.iter()
+        .find(|level| level.number == number)
+        .ok_or_else(|| ::eyre::eyre!("Level {number} is not available in this build"))
+}
+
+/// How the levels solve their subtasks
+#[derive(Copy, Clone, Default)]
+pub struct Settings<'a> {
+    /// What the solvers may spend on every subtask
+    pub budget: Budget,
+    /// Where solutions are reused from and stored, if anywhere
+    pub cache: Option<&'a Cache>,
+}
+
+/// A level wired into the solve and verify pipeline
+pub struct Level {
+    pub number: usize,
+    pub name: &'static str,
+    pub tasks: &'static [&'static str],
+    pub inputs: &'static Inputs,
+    pub solve: fn(&str, &Settings, &mut TaskReport) -> Result<String, Vec<Report>>,
+    pub verify: fn(&str, &str, &mut TaskReport) -> Result<(), Vec<Report>>,
+}
+
+impl Level {
+    pub fn input_path(&self, task: &str) -> PathBuf {
+        Path::new(INPUT_DIR).join(format!("{}_{task}.in", self.name))
+    }
+
+    pub fn output_path(&self, task: &str) -> PathBuf {
+        self.output_path_in(&Path::new(OUTPUT_DIR).join(self.name), task)
+    }
+
+    /// The output file of a task in another directory than `out/levelN`
+    pub fn output_path_in(&self, dir: &Path, task: &str) -> PathBuf {
+        dir.join(format!("{}_{task}.out", self.name))
+    }
+
+    /// Finds the task a file like `level2_3.in` or `level2_3.out` belongs to
+    pub fn task_of(&self, path: &Path, extension: &str) -> Option<usize> {
+        let file_name = path.file_name()?.to_str()?;
+        let task = file_name.strip_prefix(self.name)?
+            .strip_prefix('_')?
+            .strip_suffix(extension)?
+            .strip_suffix('.')?;
+
+        self.tasks.iter().position(|name| *name == task)
+    }
+
+    /// Solves every task with the inputs compiled into the binary
+    pub fn run(&self, run: &mut RunReport, settings: &Settings) -> Result<(), Vec<Report>> {
+        self.run_with(run, settings, |task| Ok(Cow::Borrowed(self.inputs.tasks[task])))
+    }
+
+    /// Solves every task with the inputs currently in the `inputs` directory
+    pub fn run_from_disk(&self, run: &mut RunReport, settings: &Settings) -> Result<(), Vec<Report>> {
+        self.run_with(run, settings, |task| {
+            let path = self.input_path(self.tasks[task]);
+            std::fs::read_to_string(&path)
+                .map(Cow::Owned)
+                .wrap_err(format!("Cannot read '{}'", path.display()))
+        })
+    }
+
+    fn run_with(&self, run: &mut RunReport, settings: &Settings, input: impl Fn(usize) -> eyre::Result<Cow<'static, str>>) -> Result<(), Vec<Report>> {
+        let _span = ::tracing::info_span!("level", level = self.name).entered();
+        let start = Instant::now();
+        let mut level = LevelReport::new(self.number, self.name);
+
+        let output_dir = Path::new(OUTPUT_DIR).join(self.name);
+        if let Err(error) = std::fs::create_dir_all(&output_dir) {
+            let error = Report::new(error).wrap_err(format!("Cannot create '{}' directory", output_dir.display()));
+            let error = level.fail(Status::WriteFailed, error);
+            run.levels.push(level);
+            return Err(vec![error]);
+        }
+
+        let mut errors = Vec::new();
+        for n in 0..self.tasks.len() {
+            let _span = ::tracing::info_span!("task", task = n + 1).entered();
+            let task_start = Instant::now();
+            let mut task = TaskReport::new(n + 1);
+
+            let result = input(n)
+                .map_err(|error| vec![task.fail(Status::ParseFailed, error)])
+                .and_then(|raw_input| (self.solve)(&raw_input, settings, &mut task))
+                .map_err(|errors| {
+                    errors.into_iter()
+                        .map(|error| error.wrap_err(format!("Failed to run task {}", n + 1)))
+                        .collect()
+                });
+
+            let result = result.and_then(|output| {
+                let out = self.output_path(self.tasks[n]);
+                ::tracing::info!("Writing {}", out.display());
+                std::fs::write(&out, output)
+                    .wrap_err(format!("Cannot write to '{}'", out.display()))
+                    .map(|_| task.output = Some(out.display().to_string()))
+                    .map_err(|error| vec![task.fail(Status::WriteFailed, error)])
+            });
+
+            task.duration_ms = millis(task_start.elapsed());
+            level.push(task);
+
+            if let Err(mut e) = result {
+                errors.append(&mut e);
+            }
+        }
+
+        level.duration_ms = millis(start.elapsed());
+        run.levels.push(level);
+
+        match errors.len() {
+            0 => Ok(()),
+            _ => Err(
+                errors.into_iter()
+                    .map(|error| error.wrap_err(format!("Failed to run {}", self.name)))
+                    .collect()
+            ),
+        }
+    }
+
+    /// Verifies an output file of a task against the task input in the `inputs` directory
+    pub fn verify_file(&self, task: usize, output: &Path) -> (TaskReport, Result<(), Vec<Report>>) {
+        let _span = ::tracing::info_span!("level", level = self.name).entered();
+        let _task_span = ::tracing::info_span!("task", task = task + 1).entered();
+        let start = Instant::now();
+        let mut report = TaskReport::new(task + 1);
+        report.output = Some(output.display().to_string());
+
+        let input_path = self.input_path(self.tasks[task]);
+        let result = std::fs::read_to_string(&input_path)
+            .wrap_err(format!("Cannot read '{}'", input_path.display()))
+            .map_err(|error| vec![report.fail(Status::ParseFailed, error)])
+            .and_then(|input| {
+                let output = std::fs::read_to_string(output)
+                    .wrap_err(format!("Cannot read '{}'", output.display()))
+                    .map_err(|error| vec![report.fail(Status::VerifyFailed, error)])?;
+                (self.verify)(&input, &output, &mut report)
+            });
+
+        report.duration_ms = millis(start.elapsed());
+        (report, result)
+    }
+}
+
 levels!(
     level1("level1", 1, [])
     level2("level2", 2, [])
+    level3("level3", 3, [])
+    level4("level4", 4, [])
+    level5("level5", 5, [])
 );
 
@@ -1,12 +1,19 @@
 use std::str::Lines;
 use eyre::{eyre, Context};
 use itertools::Itertools;
+use crate::budget::Meter;
+use crate::cache::Cacheable;
 use crate::input::{CountedInput, FromLines, Subtask};
+use crate::output::Verification;
+use crate::room::DeskShape;
+
+pub const DESK: DeskShape = DeskShape::new(3, 1);
 
 #[derive(Copy, Clone, Eq, PartialEq, Debug)]
 pub struct Room {
     width: usize,
     height: usize,
+    desk: DeskShape,
 }
 
 impl Subtask for Room { }
@@ -15,19 +22,36 @@ impl FromLines for Room {
     fn from_lines(lines: &mut Lines) -> eyre::Result<Self> {
         let line = lines.next().ok_or(eyre!("No line available to parse"))?;
 
-        let (width, height) = line.split(" ").next_tuple().ok_or(eyre!("Room line does not contain a tuple"))?;
+        let mut values = line.split(" ");
+        let (width, height) = values.next_tuple().ok_or(eyre!("Room line does not contain a tuple"))?;
 
         Ok(Room {
             width: width.parse().wrap_err("Failed to parse room width")?,
             height: height.parse().wrap_err("Failed to parse room height")?,
+            desk: DeskShape::parse_column(values.next(), DESK)?,
         })
     }
 }
 
+impl Cacheable for Room {
+    fn transposed(&self) -> Self {
+        Room { width: self.height, height: self.width, ..*self }
+    }
+
+    fn is_canonical(&self) -> bool {
+        self.width <= self.height
+    }
+
+    /// Desk counts do not depend on the orientation of the room
+    fn transpose_output(&self, output: &str) -> eyre::Result<String> {
+        Ok(output.to_string())
+    }
+}
+
 pub type Input = CountedInput<Room>;
 
-pub fn map(room: &Room) -> eyre::Result<String> {
-    Ok((room.width / 3 * room.height).to_string())
+pub fn map(room: &Room, _meter: &Meter) -> eyre::Result<String> {
+    Ok((room.width / room.desk.length * (room.height / room.desk.thickness)).to_string())
 }
 
 pub fn reduce(results: Vec<String>) -> String {
@@ -38,13 +62,22 @@ pub fn reduce(results: Vec<String>) -> String {
     result
 }
 
-pub fn verify(_input: &Room, output: &str) -> eyre::Result<()> {
+pub fn verify(_input: &Room, output: &str) -> eyre::Result<Verification> {
     output.parse::<usize>()
-        .map(|_| ())
+        .map(Verification::placed)
         .map_err(|_| eyre!("Output is not an usize"))
 }
 
-#[cfg(test)]
-pub fn split_example(input: &str) -> impl Iterator<Item=&str> {
+pub fn split_output(input: &str) -> impl Iterator<Item=&str> {
     input.lines().filter(|line| !line.trim().is_empty())
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::levels::level1;
+
+    #[test]
+    pub fn test_custom_desk_shapes() {
+        assert_eq!(level1("2\n9 4 4\n9 4 3x2\n").unwrap(), "8\r\n6\r\n");
+    }
+}
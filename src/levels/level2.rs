@@ -1,15 +1,22 @@
+use crate::budget::Meter;
+use crate::cache::Cacheable;
 use crate::input::{CountedInput, FromLines, Subtask};
+use crate::layout::{split_rooms, Layout};
+use crate::output::Verification;
+use crate::room::DeskShape;
 use eyre::{eyre, WrapErr};
 use itertools::Itertools;
-use std::collections::HashSet;
 use std::iter;
 use std::str::Lines;
 
+pub const DESK: DeskShape = DeskShape::new(3, 1);
+
 #[derive(Debug, Copy, Clone, Eq, PartialEq)]
 pub struct Room {
     width: usize,
     height: usize,
     desk_count: usize,
+    desk: DeskShape,
 }
 
 impl Subtask for Room {}
@@ -26,25 +33,45 @@ impl FromLines for Room {
             width: width.parse().wrap_err("Invalid width")?,
             height: height.parse().wrap_err("Invalid height")?,
             desk_count: desk_count.parse().wrap_err("Invalid desk count")?,
+            desk: DeskShape::parse_column(values.next(), DESK)?,
         })
     }
 }
 
+impl Cacheable for Room {
+    fn transposed(&self) -> Self {
+        Room { width: self.height, height: self.width, ..*self }
+    }
+
+    fn is_canonical(&self) -> bool {
+        self.width <= self.height
+    }
+
+    fn transpose_output(&self, output: &str) -> eyre::Result<String> {
+        let mut result = Layout::parse_ids(self.height, self.width, output)?.transposed().ids();
+        result.push_str("\r\n");
+        Ok(result)
+    }
+}
+
 pub type Input = CountedInput<Room>;
 
-pub fn map(room: &Room) -> eyre::Result<String> {
+pub fn map(room: &Room, _meter: &Meter) -> eyre::Result<String> {
+    let DeskShape { length, thickness } = room.desk;
     let mut id = 1;
     let mut room_result = Vec::new();
-    for _ in 0..room.height {
+    for _ in 0..room.height / thickness {
         let mut row = Vec::new();
-        for _ in 0..room.width/3 {
+        for _ in 0..room.width / length {
             // build desk
-            let desk = iter::repeat(id).take(3).join(" ");
+            let desk = iter::repeat_n(id, length).join(" ");
             row.push(desk);
             id += 1;
         }
-        room_result.push(row.join(" "));
+        row.extend(iter::repeat_n("0".to_string(), room.width % length));
+        room_result.extend(iter::repeat_n(row.join(" "), thickness));
     }
+    room_result.extend(iter::repeat_n(iter::repeat_n(0, room.width).join(" "), room.height % thickness));
 
     let mut result = room_result.join("\r\n");
     result.push_str("\r\n");
@@ -55,101 +82,28 @@ pub fn reduce(results: Vec<String>) -> String {
     results.into_iter().join("\r\n")
 }
 
-pub fn verify(input: &Room, output: &str) -> eyre::Result<()> {
-    let output_height = output.lines().count();
-    if output_height != input.height {
-        return Err(eyre!("Output does not match room height"));
-    }
-
-    let mut matrix = Vec::with_capacity(input.width * input.height);
-
-    for row in output.lines() {
-        let mut desk_ids= row.split(' ').map(|item|
-                item.parse::<usize>()
-                    .wrap_err("The desk id of a cell is not numeric")
-            )
-            .collect::<Result<Vec<_>, _>>()?;
-
-        if desk_ids.len() != input.width {
-            return Err(eyre!("Output contains {} desk ids for a {} wide room", desk_ids.len(), input.width));
-        }
+pub fn verify(input: &Room, output: &str) -> eyre::Result<Verification> {
+    let layout = Layout::parse_ids(input.width, input.height, output)?;
+    layout.check_shape(input.desk)?;
 
-        matrix.append(&mut desk_ids);
+    if layout.len() != input.desk_count {
+        return Err(eyre!("Placed desk count {} does not match input desk count {}", layout.len(), input.desk_count));
     }
 
-    let mut encountered_ids = HashSet::new();
-
-    for row in (0..(input.height * input.width)).step_by(input.width) {
-        'matrix: for col in 0..input.width - 2 {
-            if matrix[row + col] == 0 {
-                continue;
-            }
-            let id = matrix[row + col];
-
-            let positions = (0..3)
-                .map(|val| row + col + val);
-
-            for position in positions.clone() {
-                if matrix[position] != id {
-                    continue 'matrix;
-                }
-            }
-
-            for position in positions {
-                matrix[position] = 0;
-            }
-
-            if encountered_ids.contains(&id) {
-                return Err(eyre!("Encountered desk id {id} twice"));
-            }
-            encountered_ids.insert(id);
-        }
-    }
-
-    for row in (0..((input.height - 2) * input.width)).step_by(input.width) {
-        'matrix: for col in 0..input.width {
-            if matrix[row + col] == 0 {
-                continue;
-            }
-            let id = matrix[row + col];
-
-            let positions = (0..3 * input.width)
-                .step_by(input.width)
-                .map(|val| row + col + val);
-
-            for position in positions.clone() {
-                if matrix[position] != id {
-                    continue 'matrix;
-                }
-            }
-
-            for position in positions {
-                matrix[position] = 0;
-            }
-
-            if encountered_ids.contains(&id) {
-                return Err(eyre!("Encountered desk id {id} twice"));
-            }
-            encountered_ids.insert(id);
-        }
-    }
-
-    if let Some(id) = matrix.iter().find(|item| **item != 0) {
-        return if encountered_ids.contains(id) {
-            Err(eyre!("Desk {id} has more ids than it should"))
-        } else {
-            Err(eyre!("Invalid desk {id} encountered"))
-        }
-    }
-
-    if encountered_ids.len() != input.desk_count {
-        return Err(eyre!("Placed desk count {} does not match input desk count {}", encountered_ids.len(), input.desk_count));
-    }
+    Ok(Verification::required(layout.len(), input.desk_count))
+}
 
-    Ok(())
+pub fn split_output(output: &str) -> impl Iterator<Item = String> + '_ {
+    split_rooms(output)
 }
 
 #[cfg(test)]
-pub fn split_example(input: &str) -> impl Iterator<Item = &str> {
-    input.split("\r\n\r\n")
+mod tests {
+    use crate::levels::level2;
+
+    #[test]
+    pub fn test_custom_desk_shapes() {
+        assert_eq!(level2("1\n8 2 4 4\n").unwrap(), "1 1 1 1 2 2 2 2\r\n3 3 3 3 4 4 4 4\r\n");
+        level2("1\n6 4 4 3x2\n").unwrap();
+    }
 }
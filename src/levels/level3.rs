@@ -1,96 +1,140 @@
-use std::{iter, str::FromStr};
-
+use crate::budget::Meter;
+use crate::cache::Cacheable;
+use crate::input::{CountedInput, FromLines, Subtask};
+use crate::layout::{split_rooms, Layout};
+use crate::output::Verification;
+use crate::room::DeskShape;
+use eyre::{eyre, WrapErr};
 use itertools::Itertools;
+use std::iter;
+use std::str::Lines;
 
-struct Input {
-    count: usize,
-    rooms: Vec<Room>,
-}
-
-impl FromStr for Input {
-    type Err = eyre::Error;
+pub const DESK: DeskShape = DeskShape::new(3, 1);
 
-    fn from_str(s: &str) -> Result<Self, Self::Err> {
-        let mut lines = s.lines();
-        let first_line = lines.next().ok_or(eyre::eyre!("No first count line"))?;
-        let count = first_line.parse()?;
-        let rooms = lines.map(Room::from_str).collect::<Result<Vec<_>, _>>()?;
-
-        Ok(Input { count, rooms })
-    }
-}
-
-struct Room {
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct Room {
     width: usize,
     height: usize,
     desk_count: usize,
+    desk: DeskShape,
 }
 
-impl FromStr for Room {
-    type Err = eyre::Error;
+impl Subtask for Room {}
 
-    fn from_str(s: &str) -> Result<Self, Self::Err> {
-        let mut values = s.split(" ");
-        let width = values.next().ok_or(eyre::eyre!("Invalid width"))?;
-        let height = values.next().ok_or(eyre::eyre!("Invalid height"))?;
-        let desk_count = values.next().ok_or(eyre::eyre!("Invalid desk count"))?;
+impl FromLines for Room {
+    fn from_lines(lines: &mut Lines) -> Result<Self, eyre::Report> {
+        let line = lines.next().ok_or(eyre!("No line available to parse"))?;
+        let mut values = line.split(" ");
+        let width = values.next().ok_or(eyre!("Missing width"))?;
+        let height = values.next().ok_or(eyre!("Missing height"))?;
+        let desk_count = values.next().ok_or(eyre!("Missing desk count"))?;
 
         Ok(Room {
-            width: width.parse()?,
-            height: height.parse()?,
-            desk_count: desk_count.parse()?,
+            width: width.parse().wrap_err("Invalid width")?,
+            height: height.parse().wrap_err("Invalid height")?,
+            desk_count: desk_count.parse().wrap_err("Invalid desk count")?,
+            desk: DeskShape::parse_column(values.next(), DESK)?,
         })
     }
 }
 
+impl Cacheable for Room {
+    fn transposed(&self) -> Self {
+        Room { width: self.height, height: self.width, ..*self }
+    }
+
+    fn is_canonical(&self) -> bool {
+        self.width <= self.height
+    }
+
+    fn transpose_output(&self, output: &str) -> eyre::Result<String> {
+        Ok(Layout::parse_ids(self.height, self.width, output)?.transposed().ids())
+    }
+}
+
+pub type Input = CountedInput<Room>;
+
 impl Room {
+    /// Fills the room with horizontal tables in blocks of one desk length, putting vertical
+    /// tables into the columns to the right that are too narrow for another horizontal one
     pub fn generate_room_table_matrix(&self) -> String {
+        let DeskShape { length, thickness } = self.desk;
         let mut matrix = Vec::new();
         let mut table_id_counter = 1;
 
-        let full_width = self.width / 3;
-        let corner_table_count = self.width % 3;
+        let full_width = self.width / length;
+        let corner_table_count = self.width % length / thickness;
+        let corner_rest = self.width % length % thickness;
 
-        for _ in 0..self.height / 3 {
-            let horizontal_tables = 3 * full_width;
+        // The cells of a row of horizontal tables, numbered from `first_id` on, or free for 0
+        let tables = |first_id: usize| (0..full_width)
+            .map(move |table| if first_id == 0 { 0 } else { first_id + table })
+            .flat_map(move |id| iter::repeat_n(id, length));
+
+        for _ in 0..self.height / length {
+            let horizontal_tables = length / thickness * full_width;
             let corner_id = table_id_counter + horizontal_tables;
-            let corner_tables = corner_id..(corner_id + corner_table_count);
-
-            for _ in 0..3 {
-                let mut matrix_row = Vec::new();
-
-                for _ in 0..full_width {
-                    // build table
-                    let table = iter::repeat(table_id_counter).take(3).join(" ");
-                    matrix_row.push(table);
-                    table_id_counter += 1;
-                }
-                matrix_row.push(corner_tables.clone().into_iter().join(" "));
-                matrix.push(matrix_row.join(" "));
+            let corner_tables = (corner_id..(corner_id + corner_table_count))
+                .flat_map(|id| iter::repeat_n(id, thickness))
+                .chain(iter::repeat_n(0, corner_rest));
+
+            for row in 0..length {
+                // build tables, rows too few for another table stay free
+                let first_id = if row / thickness < length / thickness { table_id_counter + row / thickness * full_width } else { 0 };
+                matrix.push(tables(first_id).chain(corner_tables.clone()).join(" "));
             }
-            table_id_counter += corner_table_count;
+            table_id_counter += horizontal_tables + corner_table_count;
         }
 
-        for _ in 0..self.height % 3 {
-            let mut matrix_row = Vec::new();
-
-            for _ in 0..self.width / 3 {
-                matrix_row.push(iter::repeat(table_id_counter).take(3).join(" "));
-                table_id_counter += 1;
-            }
-            matrix_row.push(iter::repeat(0).take(self.width % 3).join(" "));
-
-            matrix.push(matrix_row.join(" "));
+        let rest_height = self.height % length;
+        for row in 0..rest_height {
+            let first_id = if row / thickness < rest_height / thickness { table_id_counter + row / thickness * full_width } else { 0 };
+            matrix.push(tables(first_id).chain(iter::repeat_n(0, self.width % length)).join(" "));
         }
 
         matrix.join("\r\n")
     }
 }
 
-pub fn run(input: &'static str) -> eyre::Result<String> {
-    let input: Input = input.parse()?;
+pub fn map(room: &Room, _meter: &Meter) -> eyre::Result<String> {
+    let mut layout = Layout::parse_ids(room.width, room.height, &room.generate_room_table_matrix())?;
+
+    if layout.len() < room.desk_count {
+        return Err(eyre!(
+            "Not all desks were placed in the room ({} out of {}, room size {}x{})",
+            layout.len(), room.desk_count, room.width, room.height,
+        ));
+    }
+    layout.truncate(room.desk_count);
+
+    Ok(layout.ids())
+}
+
+pub fn reduce(results: Vec<String>) -> String {
+    results.join("\r\n\r\n")
+}
+
+pub fn verify(input: &Room, output: &str) -> eyre::Result<Verification> {
+    let layout = Layout::parse_ids(input.width, input.height, output)?;
+    layout.check_shape(input.desk)?;
+
+    if layout.len() != input.desk_count {
+        return Err(eyre!("Placed desk count {} does not match input desk count {}", layout.len(), input.desk_count));
+    }
+
+    Ok(Verification::required(layout.len(), input.desk_count))
+}
+
+pub fn split_output(output: &str) -> impl Iterator<Item = String> + '_ {
+    split_rooms(output)
+}
 
-    let result = input.rooms.iter().map(|room| room.generate_room_table_matrix());
+#[cfg(test)]
+mod tests {
+    use crate::levels::level3;
 
-    Ok(result.into_iter().join("\r\n\r\n"))
+    #[test]
+    pub fn test_custom_desk_shapes() {
+        level3("2\n9 5 11 4\n8 8 8 4x2\n").unwrap();
+    }
 }
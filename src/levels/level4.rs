@@ -1,60 +1,72 @@
-use std::{char::UNICODE_VERSION, iter, str::FromStr};
-
-use itertools::Itertools;
-
-struct Input {
-    count: usize,
-    rooms: Vec<Room>,
-}
-
-impl FromStr for Input {
-    type Err = eyre::Error;
-
-    fn from_str(s: &str) -> Result<Self, Self::Err> {
-        let mut lines = s.lines();
-        let first_line = lines.next().ok_or(eyre::eyre!("No first count line"))?;
-        let count = first_line.parse()?;
-        let rooms = lines.map(Room::from_str).collect::<Result<Vec<_>, _>>()?;
-
-        Ok(Input { count, rooms })
-    }
-}
-
-struct Room {
+use crate::budget::Meter;
+use crate::cache::Cacheable;
+use crate::input::{CountedInput, FromLines, Subtask};
+use crate::layout::{split_rooms, Layout};
+use crate::output::Verification;
+use crate::room::DeskShape;
+use eyre::{eyre, WrapErr};
+use std::str::Lines;
+
+pub const DESK: DeskShape = DeskShape::new(3, 1);
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct Room {
     width: usize,
     height: usize,
     desk_count: usize,
+    desk: DeskShape,
 }
 
-impl FromStr for Room {
-    type Err = eyre::Error;
+impl Subtask for Room {}
 
-    fn from_str(s: &str) -> Result<Self, Self::Err> {
-        let mut values = s.split(" ");
-        let width = values.next().ok_or(eyre::eyre!("Invalid width"))?;
-        let height = values.next().ok_or(eyre::eyre!("Invalid height"))?;
-        let desk_count = values.next().ok_or(eyre::eyre!("Invalid desk count"))?;
+impl FromLines for Room {
+    fn from_lines(lines: &mut Lines) -> Result<Self, eyre::Report> {
+        let line = lines.next().ok_or(eyre!("No line available to parse"))?;
+        let mut values = line.split(" ");
+        let width = values.next().ok_or(eyre!("Missing width"))?;
+        let height = values.next().ok_or(eyre!("Missing height"))?;
+        let desk_count = values.next().ok_or(eyre!("Missing desk count"))?;
 
         Ok(Room {
-            width: width.parse()?,
-            height: height.parse()?,
-            desk_count: desk_count.parse()?,
+            width: width.parse().wrap_err("Invalid width")?,
+            height: height.parse().wrap_err("Invalid height")?,
+            desk_count: desk_count.parse().wrap_err("Invalid desk count")?,
+            desk: DeskShape::parse_column(values.next(), DESK)?,
         })
     }
 }
 
-const TABLE_WIDTH: usize = 4;
-const TABLE_HEIGHT: usize = 2;
+impl Cacheable for Room {
+    fn transposed(&self) -> Self {
+        Room { width: self.height, height: self.width, ..*self }
+    }
+
+    fn is_canonical(&self) -> bool {
+        self.width <= self.height
+    }
+
+    fn transpose_output(&self, output: &str) -> eyre::Result<String> {
+        Ok(Layout::parse_marks(self.height, self.width, output)?.transposed().marks())
+    }
+}
+
+pub type Input = CountedInput<Room>;
 
 impl Room {
+    /// Places vertical tables in every other column, in blocks of one table length followed by a
+    /// free row, and a row of horizontal tables into the rows left at the bottom
     pub fn generate_room_table_matrix(&self) -> eyre::Result<String> {
+        let DeskShape { length, thickness } = self.desk;
         let mut matrix = Vec::new();
-        let number_of_vertical_blocks = self.height / 4;
+        let number_of_vertical_blocks = self.height / (length + 1);
 
         let single_line = {
-            let mut single_line = "X.".repeat(self.width / 2);
-            if self.width % 2 == 1 {
-                single_line.push_str("X");
+            let mut single_line = format!("{}.", "X".repeat(thickness)).repeat(self.width / (thickness + 1));
+            let rest_width = self.width % (thickness + 1);
+            if rest_width == thickness {
+                single_line.push_str(&"X".repeat(thickness));
+            } else {
+                single_line.push_str(&".".repeat(rest_width));
             }
             single_line
         };
@@ -62,7 +74,7 @@ impl Room {
         let trimmed_block: Vec<String> = {
             let mut lines = Vec::new();
 
-            for _ in 0..3 {
+            for _ in 0..length {
                 lines.push(single_line.clone());
             }
 
@@ -79,32 +91,38 @@ impl Room {
             matrix.append(&mut block.clone());
         }
 
-        let rest_height = self.height % 4;
+        let rest_height = self.height % (length + 1);
 
-        if rest_height == 3 {
+        if rest_height == length {
             matrix.append(&mut trimmed_block.clone());
-        } else if rest_height > 0 {
+        } else if rest_height >= thickness {
             let mut row = String::new();
-            let number_of_horizontal_blocks = self.width / 4;
+            let number_of_horizontal_blocks = self.width / (length + 1);
 
-            let trimmed_table = "XXX";
-            let table = "XXX.";
+            let trimmed_table = "X".repeat(length);
+            let table = format!("{trimmed_table}.");
 
             for _ in 0..number_of_horizontal_blocks {
-                row.push_str(table);
+                row.push_str(&table);
             }
 
-            let rest_width = self.width - number_of_horizontal_blocks * 4;
+            let rest_width = self.width - number_of_horizontal_blocks * (length + 1);
 
-            if rest_width == 3 {
-                row.push_str(trimmed_table);
+            if rest_width == length {
+                row.push_str(&trimmed_table);
             } else {
                 row.push_str(&".".repeat(rest_width));
             }
 
-            matrix.push(row);
+            for _ in 0..thickness {
+                matrix.push(row.clone());
+            }
 
-            if rest_height == 2 {
+            for _ in thickness..rest_height {
+                matrix.push(".".repeat(self.width));
+            }
+        } else {
+            for _ in 0..rest_height {
                 matrix.push(".".repeat(self.width));
             }
         };
@@ -113,18 +131,50 @@ impl Room {
     }
 }
 
-pub fn run(input: &'static str) -> eyre::Result<String> {
-    let input: Input = input.parse()?;
+pub fn map(room: &Room, _meter: &Meter) -> eyre::Result<String> {
+    let mut layout = Layout::parse_marks(room.width, room.height, &room.generate_room_table_matrix()?)?;
 
-    let mut result = input
-        .rooms
-        .iter()
-        .map(|room| room.generate_room_table_matrix())
-        .collect::<Result<Vec<_>, _>>()?
-        .iter()
-        .join("\r\n\r\n");
+    if layout.len() < room.desk_count {
+        return Err(eyre!(
+            "Not all desks were placed in the room ({} out of {}, room size {}x{}): \n{}",
+            layout.len(), room.desk_count, room.width, room.height, layout.marks(),
+        ));
+    }
+    layout.truncate(room.desk_count);
+
+    Ok(layout.marks())
+}
+
+pub fn reduce(results: Vec<String>) -> String {
+    let mut result = results.join("\r\n\r\n");
 
     result.push_str("\r\n\r\n");
 
-    Ok(result)
+    result
+}
+
+pub fn verify(input: &Room, output: &str) -> eyre::Result<Verification> {
+    let layout = Layout::parse_marks(input.width, input.height, output)?;
+    layout.check_shape(input.desk)?;
+    layout.check_spacing()?;
+
+    if layout.len() != input.desk_count {
+        return Err(eyre!("Placed desk count {} does not match input desk count {}", layout.len(), input.desk_count));
+    }
+
+    Ok(Verification::required(layout.len(), input.desk_count))
+}
+
+pub fn split_output(output: &str) -> impl Iterator<Item = String> + '_ {
+    split_rooms(output)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::levels::level4;
+
+    #[test]
+    pub fn test_custom_desk_shapes() {
+        level4("2\n9 5 5 4\n9 9 6 2x2\n").unwrap();
+    }
 }
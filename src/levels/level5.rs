@@ -1,84 +1,85 @@
-use std::{char::UNICODE_VERSION, iter, str::FromStr};
-
-use color_eyre::owo_colors::OwoColorize;
+use crate::bound::{self, Bound};
+use crate::budget::Meter;
+use crate::cache::Cacheable;
+use crate::exact;
+use crate::input::{CountedInput, FromLines, Subtask};
+use crate::layout::{split_rooms, Desk, Layout};
+use crate::sat;
+use crate::output::{Solution, Verification};
+use crate::room::{DeskShape, FloorPlan, Rules, Spacing};
+use crate::search::{Improvement, LocalSearch};
+use crate::tiling::Tiling;
+use eyre::{eyre, WrapErr};
 use itertools::Itertools;
+use std::iter;
+use std::str::Lines;
 
-struct Input {
-    count: usize,
-    rooms: Vec<Room>,
-}
-
-impl FromStr for Input {
-    type Err = eyre::Error;
-
-    fn from_str(s: &str) -> Result<Self, Self::Err> {
-        let mut lines = s.lines();
-        let first_line = lines.next().ok_or(eyre::eyre!("No first count line"))?;
-        let count = first_line.parse()?;
-        let rooms = lines.map(Room::from_str).collect::<Result<Vec<_>, _>>()?;
-
-        Ok(Input { count, rooms })
-    }
-}
+pub const DESK: DeskShape = DeskShape::new(2, 1);
 
-struct Room {
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct Room {
     width: usize,
     height: usize,
     desk_count: usize,
+    desk: DeskShape,
+    rules: Rules,
 }
 
-impl FromStr for Room {
-    type Err = eyre::Error;
+impl Subtask for Room {}
 
-    fn from_str(s: &str) -> Result<Self, Self::Err> {
-        let mut values = s.split(" ");
-        let width = values.next().ok_or(eyre::eyre!("Invalid width"))?;
-        let height = values.next().ok_or(eyre::eyre!("Invalid height"))?;
-        let desk_count = values.next().ok_or(eyre::eyre!("Invalid desk count"))?;
+impl FromLines for Room {
+    fn from_lines(lines: &mut Lines) -> Result<Self, eyre::Report> {
+        let line = lines.next().ok_or(eyre!("No line available to parse"))?;
+        let mut values = line.split(" ");
+        let width = values.next().ok_or(eyre!("Missing width"))?;
+        let height = values.next().ok_or(eyre!("Missing height"))?;
+        let desk_count = values.next().ok_or(eyre!("Missing desk count"))?;
+        let (desk, rules) = Rules::parse_columns(values, DESK)?;
 
         Ok(Room {
-            width: width.parse()?,
-            height: height.parse()?,
-            desk_count: desk_count.parse()?,
+            width: width.parse().wrap_err("Invalid width")?,
+            height: height.parse().wrap_err("Invalid height")?,
+            desk_count: desk_count.parse().wrap_err("Invalid desk count")?,
+            desk,
+            rules,
         })
     }
 }
 
-const TABLE_WIDTH: usize = 2;
+impl Cacheable for Room {
+    fn transposed(&self) -> Self {
+        Room { width: self.height, height: self.width, ..*self }
+    }
 
-impl Room {
-    pub fn generate_room_table_matrix(&self) -> eyre::Result<String> {
-        let results = vec![self.generate_room_table_matrix_for_uneven_width()?, self.generate_room_table_matrix_for_uneven_height()?, self.generate_room_table_matrix_for_uneven_width()?, self.generate_room_table_matrix_for_even_height()?];
-        let Some((result, number_of_placed_desks)) = results.iter().max_by(|a, b| a.1.cmp(&b.1)) else { unreachable!()};
-        if *number_of_placed_desks != self.desk_count {
-            return Err(eyre::eyre!(format!(
-                "Not all desks were placed in the room ({} out of {}, room size {}x{}): \n{}",
-                number_of_placed_desks, self.desk_count, self.width, self.height, result,
-            )));
-        }
-        Ok(result.clone())
+    fn is_canonical(&self) -> bool {
+        self.width <= self.height
     }
 
-    // fn generate_room_table_matrix_for_uneven_height_simple(&self) -> eyre::Result<(String, usize)> {
-    //     let mut matrix = Vec::new();
-    //     let mut number_of_placed_desks = 0;
+    fn transpose_output(&self, output: &str) -> eyre::Result<String> {
+        Ok(Layout::parse_rendered(self.height, self.width, output)?.under(self.rules).transposed().render())
+    }
+}
 
-    //     let line = iter::repeat("XX").take(self.width / 3).join(".");
-    //     let vertical_rest = match self.width % 3 {
-    //         1 => ".",
-    //         2 => ".X",
-    //     };
+pub type Input = CountedInput<Room>;
 
-    //     let block = {
-    //         let mut block = Vec::new();
+pub type Strategy = fn(usize, usize, DeskShape, Spacing) -> Layout;
 
-    //         block.push(format!("{line}{vertical_rest}"));
-    //         block.push(format!("{}{vertical_rest}", ".".repeat(self.width - vertical_rest.len())));
-    //         block.push(format!("{line}{}", ".".repeat)
-    //     };
-    // }
+impl Room {
+    /// A room without desks, for the patterns that do not fit the desk shape
+    fn empty_matrix(&self) -> (String, usize) {
+        (iter::repeat_n(".".repeat(self.width), self.height).join("\r\n"), 0)
+    }
+
+    /// Repeats a block of seven rows: two rows of vertical desks with two horizontal desks at
+    /// the right border, the same turned by 180 degrees and a free row
+    ///
+    /// The pattern is only known for 2x1 desks in rooms of an even width. In rooms narrower than
+    /// 6 the horizontal desks of the block and its turned copy would touch.
+    pub fn generate_room_table_matrix_for_even_width(&self) -> eyre::Result<(String, usize)> {
+        if self.desk != DESK || self.width < 6 || self.width % 2 == 1 {
+            return Ok(self.empty_matrix());
+        }
 
-    fn generate_room_table_matrix_for_even_width(&self) -> eyre::Result<(String, usize)> {
         let mut matrix = Vec::new();
         let mut number_of_placed_desks = 0;
 
@@ -86,10 +87,9 @@ impl Room {
         let block_count = self.height / block_height;
         let extra_rows = self.height % block_height;
 
+        let vertical_desks = (self.width - 2) / 2;
         let block: Vec<String> = {
             let mut block = Vec::new();
-            let horizontal_desks = (self.width - 2) / 2;
-            number_of_placed_desks += horizontal_desks;
             let mut first_line = "X.".repeat((self.width - 2) / 2);
             first_line.push_str("XX");
             block.push(first_line);
@@ -97,7 +97,6 @@ impl Room {
             second_line.push_str("..");
             block.push(second_line);
             let mut last_line = ".".repeat(self.width - 2);
-            number_of_placed_desks += 2;
             last_line.push_str("XX");
             block.push(last_line);
             block
@@ -113,21 +112,24 @@ impl Room {
             matrix.append(&mut block.clone());
             matrix.append(&mut block_mirror.clone());
             matrix.push(".".repeat(self.width));
+            number_of_placed_desks += 2 * (vertical_desks + 2);
         }
 
         let mut full_block = Vec::new();
         full_block.append(&mut block.clone());
         full_block.append(&mut block_mirror.clone());
-        if extra_rows % 2 == 0 {
+        if extra_rows.is_multiple_of(2) {
             matrix.extend_from_slice(&full_block[..extra_rows]);
-            number_of_placed_desks += (self.width / 2) * if extra_rows == 6 { 2 } else { 1 };
-            if extra_rows != 2 {
+            if extra_rows > 0 {
+                number_of_placed_desks += (self.width / 2) * if extra_rows == 6 { 2 } else { 1 };
+            }
+            if extra_rows > 2 {
                 number_of_placed_desks += 2;
             }
         } else if extra_rows == 1 {
             let mut row = String::new();
 
-            row.push_str(&iter::repeat("XX.").take(self.width / 3).join(""));
+            row.push_str(&iter::repeat_n("XX.", self.width / 3).join(""));
             number_of_placed_desks += self.width / 3;
             row.push_str(&".".repeat(self.width % 3));
             matrix.push(row);
@@ -147,107 +149,39 @@ impl Room {
         Ok((matrix.join("\r\n"), number_of_placed_desks))
     }
 
-    fn generate_room_table_matrix_for_even_height(&self) -> eyre::Result<(String, usize)> {
-        let width = self.height;
-        let height = self.width;
-        let mut matrix = Vec::new();
-        let mut number_of_placed_desks = 0;
-
-        let block_height = 7;
-        let block_count = height / block_height;
-        let extra_rows = height % block_height;
-
-        let block: Vec<String> = {
-            let mut block = Vec::new();
-            let horizontal_desks = (width - 2) / 2;
-            number_of_placed_desks += horizontal_desks;
-            let mut first_line = "X.".repeat((width - 2) / 2);
-            first_line.push_str("XX");
-            block.push(first_line);
-            let mut second_line = "X.".repeat((width - 2) / 2);
-            second_line.push_str("..");
-            block.push(second_line);
-            let mut last_line = ".".repeat(width - 2);
-            number_of_placed_desks += 2;
-            last_line.push_str("XX");
-            block.push(last_line);
-            block
-        };
-
-        let block_mirror: Vec<String> = block
-            .iter()
-            .rev()
-            .map(|line| line.chars().rev().collect::<String>())
-            .collect();
-
-        for _ in 0..block_count {
-            matrix.append(&mut block.clone());
-            matrix.append(&mut block_mirror.clone());
-            matrix.push(".".repeat(width));
+    /// [Room::generate_room_table_matrix_for_even_width] for the room turned on its side
+    pub fn generate_room_table_matrix_for_even_height(&self) -> eyre::Result<(String, usize)> {
+        if self.width == 0 || self.height == 0 {
+            return Ok(self.empty_matrix());
         }
-
-        let mut full_block = Vec::new();
-        full_block.append(&mut block.clone());
-        full_block.append(&mut block_mirror.clone());
-        if extra_rows % 2 == 0 {
-            matrix.extend_from_slice(&full_block[..extra_rows]);
-            number_of_placed_desks += (width / 2) * if extra_rows == 6 { 2 } else { 1 };
-            if extra_rows != 2 {
-                number_of_placed_desks += 2;
-            }
-        } else if extra_rows == 1 {
-            let mut row = String::new();
-
-            row.push_str(&iter::repeat("XX.").take(width / 3).join(""));
-            number_of_placed_desks += width / 3;
-            row.push_str(&".".repeat(width % 3));
-            matrix.push(row);
-        } else {
-            matrix.extend_from_slice(&full_block[..extra_rows - 1]);
-            if extra_rows == 3 {
-                number_of_placed_desks += (width / 2) + 1;
-            } else {
-                number_of_placed_desks += (width / 2) + 3;
-            }
-            let mut row = String::new();
-            row.push_str(&".".repeat(width - 2));
-            row.push_str("XX");
-            matrix.push(row);
-        }
-
-        let bytes = matrix
-            .iter()
-            .flat_map(|s| s.clone().into_bytes())
-            .collect::<Vec<u8>>();
-        let mut transposed: Vec<u8> = vec![0; bytes.len()];
-        transpose::transpose(&bytes, &mut transposed, width, height);
-        let mut matrix = transposed
-            .chunks(height)
-            .map(|chunk| String::from_utf8(chunk.to_owned()).unwrap())
-            .collect::<Vec<String>>();
-        matrix
-            .iter_mut()
-            .for_each(|string| *string = string.reversed().to_string());
-
-        Ok((matrix.join("\r\n"), number_of_placed_desks))
+        let (matrix, number_of_placed_desks) = self.transposed().generate_room_table_matrix_for_even_width()?;
+        Ok((transpose_matrix(&matrix, self.height, self.width), number_of_placed_desks))
     }
-    fn generate_room_table_matrix_for_uneven_width(&self) -> eyre::Result<(String, usize)> {
+
+    /// Places vertical desks in every other column, in blocks of one desk length followed by a
+    /// free row, and a row of horizontal desks into the rows left at the bottom
+    pub fn generate_room_table_matrix_for_uneven_width(&self) -> eyre::Result<(String, usize)> {
+        let DeskShape { length, thickness } = self.desk;
         let mut matrix = Vec::new();
         let mut number_of_placed_desks = 0;
-        let number_of_vertical_blocks = self.height / (TABLE_WIDTH + 1);
+        let number_of_vertical_blocks = self.height / (length + 1);
 
         let single_line = {
-            let mut single_line = "X.".repeat(self.width / 2);
-            if self.width % 2 == 1 {
-                single_line.push_str("X");
+            let mut single_line = format!("{}.", "X".repeat(thickness)).repeat(self.width / (thickness + 1));
+            let rest_width = self.width % (thickness + 1);
+            if rest_width == thickness {
+                single_line.push_str(&"X".repeat(thickness));
+            } else {
+                single_line.push_str(&".".repeat(rest_width));
             }
             single_line
         };
+        let desks_per_line = self.width / (thickness + 1) + usize::from(self.width % (thickness + 1) == thickness);
 
         let trimmed_block: Vec<String> = {
             let mut lines = Vec::new();
 
-            for _ in 0..TABLE_WIDTH {
+            for _ in 0..length {
                 lines.push(single_line.clone());
             }
 
@@ -262,141 +196,514 @@ impl Room {
 
         for _ in 0..number_of_vertical_blocks {
             matrix.append(&mut block.clone());
-            number_of_placed_desks += self.width / TABLE_WIDTH + self.width % TABLE_WIDTH;
+            number_of_placed_desks += desks_per_line;
         }
 
-        let rest_height = self.height % (TABLE_WIDTH + 1);
+        let rest_height = self.height % (length + 1);
 
-        if rest_height == 2 {
+        if rest_height == length {
             matrix.append(&mut trimmed_block.clone());
-            number_of_placed_desks += self.width / TABLE_WIDTH + self.width % TABLE_WIDTH;
-        } else if rest_height > 0 {
+            number_of_placed_desks += desks_per_line;
+        } else if rest_height >= thickness {
             let mut row = String::new();
-            let number_of_horizontal_blocks = self.width / (TABLE_WIDTH + 1);
+            let number_of_horizontal_blocks = self.width / (length + 1);
 
-            let trimmed_table = "XX";
-            let table = "XX.";
+            let trimmed_table = "X".repeat(length);
+            let table = format!("{trimmed_table}.");
 
             for _ in 0..number_of_horizontal_blocks {
-                row.push_str(table);
+                row.push_str(&table);
                 number_of_placed_desks += 1;
             }
 
-            let rest_width = self.width - number_of_horizontal_blocks * (TABLE_WIDTH + 1);
+            let rest_width = self.width - number_of_horizontal_blocks * (length + 1);
 
-            if rest_width == 2 {
-                row.push_str(trimmed_table);
+            if rest_width == length {
+                row.push_str(&trimmed_table);
                 number_of_placed_desks += 1;
             } else {
                 row.push_str(&".".repeat(rest_width));
             }
 
-            matrix.push(row);
+            for _ in 0..thickness {
+                matrix.push(row.clone());
+            }
+            for _ in thickness..rest_height {
+                matrix.push(".".repeat(self.width));
+            }
+        } else {
+            for _ in 0..rest_height {
+                matrix.push(".".repeat(self.width));
+            }
         };
 
         Ok((matrix.join("\r\n"), number_of_placed_desks))
     }
 
-    fn generate_room_table_matrix_for_uneven_height(&self) -> eyre::Result<(String, usize)> {
-        let width = self.height;
-        let height = self.width;
-        let mut matrix = Vec::new();
-        let mut number_of_placed_desks = 0;
-        let number_of_vertical_blocks = height / (TABLE_WIDTH + 1);
+    /// [Room::generate_room_table_matrix_for_uneven_width] for the room turned on its side
+    pub fn generate_room_table_matrix_for_uneven_height(&self) -> eyre::Result<(String, usize)> {
+        if self.width == 0 || self.height == 0 {
+            return Ok(self.empty_matrix());
+        }
+        let (matrix, number_of_placed_desks) = self.transposed().generate_room_table_matrix_for_uneven_width()?;
+        Ok((transpose_matrix(&matrix, self.height, self.width), number_of_placed_desks))
+    }
+}
 
-        let single_line = {
-            let mut single_line = "X.".repeat(width / 2);
-            if width % 2 == 1 {
-                single_line.push_str("X");
-            }
-            single_line
-        };
+/// Transposes the rows of a `width` x `height` matrix of single byte cells
+fn transpose_matrix(matrix: &str, width: usize, height: usize) -> String {
+    let bytes = matrix
+        .lines()
+        .flat_map(|line| line.bytes())
+        .collect::<Vec<u8>>();
+    let mut transposed: Vec<u8> = vec![0; bytes.len()];
+    transpose::transpose(&bytes, &mut transposed, width, height);
+    transposed
+        .chunks(height)
+        .map(|chunk| String::from_utf8(chunk.to_owned()).expect("Marks are ASCII"))
+        .join("\r\n")
+}
 
-        let trimmed_block: Vec<String> = {
-            let mut lines = Vec::new();
+/// Turns a pattern of [Room] into a strategy, keeping the desks of the desk shape
+///
+/// The patterns keep desks one cell apart, strategies remove what breaks other spacings.
+fn pattern(width: usize, height: usize, desk: DeskShape, generate: fn(&Room) -> eyre::Result<(String, usize)>) -> Layout {
+    let room = Room::new(width, height, 0, desk, Rules::default());
+    match generate(&room).and_then(|(matrix, _)| Layout::parse_marks(width, height, &matrix)) {
+        Ok(mut layout) => {
+            layout.desks.retain(|placed| desk.fits(placed.width, placed.height));
+            layout
+        }
+        Err(error) => {
+            tracing::debug!("Discarding pattern: {error}");
+            Layout::new(width, height)
+        }
+    }
+}
 
-            for _ in 0..TABLE_WIDTH {
-                lines.push(single_line.clone());
-            }
+pub fn uneven_width(width: usize, height: usize, desk: DeskShape, _spacing: Spacing) -> Layout {
+    pattern(width, height, desk, Room::generate_room_table_matrix_for_uneven_width)
+}
 
-            lines
-        };
+pub fn uneven_height(width: usize, height: usize, desk: DeskShape, _spacing: Spacing) -> Layout {
+    pattern(width, height, desk, Room::generate_room_table_matrix_for_uneven_height)
+}
 
-        let block: Vec<String> = {
-            let mut block = trimmed_block.clone();
-            block.push(".".repeat(width));
-            block
-        };
+pub fn even_width(width: usize, height: usize, desk: DeskShape, _spacing: Spacing) -> Layout {
+    pattern(width, height, desk, Room::generate_room_table_matrix_for_even_width)
+}
 
-        for _ in 0..number_of_vertical_blocks {
-            matrix.append(&mut block.clone());
-            number_of_placed_desks += width / TABLE_WIDTH + width % TABLE_WIDTH;
+pub fn even_height(width: usize, height: usize, desk: DeskShape, _spacing: Spacing) -> Layout {
+    pattern(width, height, desk, Room::generate_room_table_matrix_for_even_height)
+}
+
+/// Places vertical desks in columns `gap` cells apart, in blocks of one desk length followed by
+/// `gap` free rows, and a row of horizontal desks into the rows left at the bottom
+///
+/// Like [uneven_width] for any spacing.
+pub fn columns(width: usize, height: usize, desk: DeskShape, spacing: Spacing) -> Layout {
+    let DeskShape { length, thickness } = desk;
+    let gap = spacing.gap;
+    let mut layout = Layout::new(width, height);
+
+    let mut y = 0;
+    while y + length <= height {
+        for column in 0..(width + gap) / (thickness + gap) {
+            layout.place(Desk::new(column * (thickness + gap), y, desk.vertical()));
         }
+        y += length + gap;
+    }
 
-        let rest_height = height % (TABLE_WIDTH + 1);
+    if y < height && height - y >= thickness {
+        for column in 0..(width + gap) / (length + gap) {
+            layout.place(Desk::new(column * (length + gap), y, desk.horizontal()));
+        }
+    }
 
-        if rest_height == 2 {
-            matrix.append(&mut trimmed_block.clone());
-            number_of_placed_desks += width / TABLE_WIDTH + width % TABLE_WIDTH;
-        } else if rest_height > 0 {
-            let mut row = String::new();
-            let number_of_horizontal_blocks = width / (TABLE_WIDTH + 1);
+    layout
+}
 
-            let trimmed_table = "XX";
-            let table = "XX.";
+/// [columns] for the room turned on its side
+pub fn rows(width: usize, height: usize, desk: DeskShape, spacing: Spacing) -> Layout {
+    columns(height, width, desk, spacing).transposed()
+}
 
-            for _ in 0..number_of_horizontal_blocks {
-                row.push_str(table);
-                number_of_placed_desks += 1;
-            }
+/// The hand-derived patterns first, so that they are credited for the layouts they find, then
+/// their generalisations to other spacings
+pub const STRATEGIES: [(&str, Strategy); 6] = [
+    ("uneven_width", uneven_width),
+    ("uneven_height", uneven_height),
+    ("even_width", even_width),
+    ("even_height", even_height),
+    ("columns", columns),
+    ("rows", rows),
+];
+
+/// The layout picked for a floor plan and the strategy that found it
+pub struct Placement {
+    pub strategy: &'static str,
+    pub layout: Layout,
+    /// Set if a local search added desks to the layout the strategies found
+    pub improvement: Option<Improvement>,
+}
 
-            let rest_width = width - number_of_horizontal_blocks * (TABLE_WIDTH + 1);
+/// Runs every strategy, completes its layout greedily and keeps the one with the most desks
+///
+/// Desks a strategy puts onto blocked cells, into the walkway or without a chair are removed
+/// before the greedy completion.
+pub fn strategies(plan: &FloorPlan) -> (&'static str, Layout) {
+    let mut best = ("greedy", Layout::with_floor(&plan.floor).under(plan.rules));
+    best.1.fill_greedy(plan.desk);
+
+    for (name, strategy) in STRATEGIES {
+        let _span = tracing::debug_span!("strategy", name).entered();
+        let mut layout = strategy(plan.width, plan.height, plan.desk, plan.rules.spacing)
+            .on(&plan.floor)
+            .under(plan.rules);
+        layout.remove_blocked();
+        layout.retain_rules();
+        layout.fill_greedy(plan.desk);
+
+        if let Err(error) = layout.check_spacing() {
+            tracing::debug!("Discarding invalid layout: {error}");
+            continue;
+        }
+        tracing::debug!(desks = layout.len(), "Strategy finished");
 
-            if rest_width == 2 {
-                row.push_str(trimmed_table);
-                number_of_placed_desks += 1;
-            } else {
-                row.push_str(&".".repeat(rest_width));
-            }
+        if layout.len() > best.1.len() {
+            best = (name, layout);
+        }
+    }
 
-            matrix.push(row);
-        };
+    best
+}
 
-        let bytes = matrix
-            .iter()
-            .flat_map(|s| s.clone().into_bytes())
-            .collect::<Vec<u8>>();
-        let mut transposed: Vec<u8> = vec![0; bytes.len()];
-        transpose::transpose(&bytes, &mut transposed, width, height);
-        let mut matrix = transposed
-            .chunks(height)
-            .map(|chunk| String::from_utf8(chunk.to_owned()).unwrap())
-            .collect::<Vec<String>>();
-        matrix
-            .iter_mut()
-            .for_each(|string| *string = string.reversed().to_string());
+/// Composes the room of the optimal layouts of small rooms, see [Tiling]
+fn tiled(plan: &FloorPlan) -> eyre::Result<Layout> {
+    let _span = tracing::debug_span!("strategy", name = "tiling").entered();
+    let mut layout = Tiling::of(plan.desk, plan.rules)?
+        .compose(plan.width, plan.height)?
+        .on(&plan.floor)
+        .under(plan.rules);
+    layout.remove_blocked();
+    layout.retain_rules();
+    layout.fill_greedy(plan.desk);
+    tracing::debug!(desks = layout.len(), "Strategy finished");
+
+    Ok(layout)
+}
 
-        Ok((matrix.join("\r\n"), number_of_placed_desks))
+/// Places desks by the [strategies], and if they fall short by tiling the room with small
+/// optimal layouts and by searching small rooms exhaustively
+///
+/// If the best layout still falls short, a local search tries to make room for the missing
+/// desks. Both searches stop with the best layout found so far when the budget runs out.
+pub fn place_desks(plan: &FloorPlan, meter: &Meter) -> Placement {
+    let mut best = strategies(plan);
+
+    if best.1.len() < plan.desk_count {
+        match tiled(plan) {
+            Ok(layout) if layout.len() > best.1.len() => best = ("tiling", layout),
+            Ok(_) => {},
+            Err(error) => tracing::debug!("Discarding tiling: {error}"),
+        }
+    }
+
+    if best.1.len() < plan.desk_count && plan.width * plan.height <= exact::CELL_LIMIT {
+        let _span = tracing::debug_span!("strategy", name = "exact").entered();
+        let layout = exact::maximise(&Layout::with_floor(&plan.floor).under(plan.rules), plan.desk, meter);
+        tracing::debug!(desks = layout.len(), "Strategy finished");
+
+        if layout.len() > best.1.len() {
+            best = ("exact", layout);
+        }
+    }
+
+    let (strategy, layout) = best;
+    if layout.len() >= plan.desk_count {
+        return Placement { strategy, layout, improvement: None };
+    }
+
+    let _span = tracing::debug_span!("strategy", name = "local_search").entered();
+    let search = LocalSearch { target: Some(plan.desk_count), ..LocalSearch::default() };
+    let (improved, iterations) = search.improve(&layout, plan.desk, meter);
+    tracing::debug!(desks = improved.len(), iterations, "Strategy finished");
+
+    if improved.len() > layout.len() {
+        let improvement = Improvement { from: strategy, start: layout.len(), end: improved.len(), iterations };
+        return Placement { strategy: "local_search", layout: improved, improvement: Some(improvement) };
     }
+
+    Placement { strategy, layout, improvement: None }
 }
 
-pub fn run(input: &'static str) -> eyre::Result<String> {
-    let input: Input = input.parse()?;
-
-    let mut result = input
-        .rooms
-        .iter()
-        .enumerate()
-        .map(|(i, room)| {
-            room.generate_room_table_matrix().map_err(|e| {
-                e.wrap_err(format!("Failed to generate desk matrix for room {}", i + 1))
-            })
-        })
-        .collect::<Result<Vec<_>, _>>()?
-        .iter()
-        .join("\r\n\r\n");
+/// Places the desks of a floor plan, rendering blocked cells as `#` and touching desks by their
+/// ids, see [Layout::render]
+///
+/// If the strategies fall short in a small enough room that the bound does not rule out, the
+/// placement is solved as a formula, which either finds the desks a place or proves that they
+/// do not fit unless the budget runs out first.
+pub fn solve(plan: &FloorPlan, meter: &Meter) -> eyre::Result<Solution> {
+    let bound = bound::maximum(&plan.floor, plan.desk, plan.rules.spacing, &meter.sibling());
+    tracing::debug!(desks = bound.desks, method = bound.method, "Bounded room");
+
+    let Placement { mut strategy, mut layout, mut improvement } = place_desks(plan, meter);
+
+    if layout.len() < plan.desk_count
+        && bound.desks >= plan.desk_count
+        && plan.width * plan.height <= sat::CELL_LIMIT
+        && !meter.is_exhausted()
+    {
+        let _span = tracing::debug_span!("strategy", name = "sat").entered();
+        let room = Layout::with_floor(&plan.floor).under(plan.rules);
+        match sat::place(&room, plan.desk, plan.desk_count, meter) {
+            Some(placed) => (strategy, layout, improvement) = ("sat", placed, None),
+            None if meter.is_exhausted() => {},
+            None => return Err(eyre!(
+                "{} desks do not fit into the {}x{} room, the placement formula is unsatisfiable",
+                plan.desk_count, plan.width, plan.height,
+            )),
+        }
+    }
+
+    if layout.len() < plan.desk_count {
+        return Err(shortfall(plan, &layout, bound, meter));
+    }
+    layout.truncate(plan.desk_count);
+
+    Ok(Solution::with_strategy(layout.render(), strategy)
+        .with_improvement(improvement)
+        .with_bound(bound))
+}
+
+/// Explains why a layout falls short, telling an impossible desk count from solvers that are
+/// too weak for it
+fn shortfall(plan: &FloorPlan, layout: &Layout, bound: Bound, meter: &Meter) -> eyre::Report {
+    let mut error = eyre!(
+        "Not all desks were placed in the room ({} out of {}, room size {}x{}): \n{}",
+        layout.len(), plan.desk_count, plan.width, plan.height, layout.render(),
+    );
+    if meter.is_exhausted() {
+        error = error.wrap_err(format!("The budget ran out after {} search nodes", meter.spent()));
+    }
+
+    if bound.desks < plan.desk_count {
+        error.wrap_err(format!("Impossible target, at most {bound} of the {} required desks fit", plan.desk_count))
+    } else {
+        error.wrap_err(format!("Solver weakness, the bound of {bound} does not rule out the {} required desks", plan.desk_count))
+    }
+}
+
+/// Verifies the desks placed on a floor plan, including that none stands on a blocked cell and
+/// that they leave the walkway and a chair for every desk
+pub fn verify_plan(plan: &FloorPlan, output: &str) -> eyre::Result<Verification> {
+    let layout = Layout::parse_rendered(plan.width, plan.height, output)?.on(&plan.floor).under(plan.rules);
+    layout.check_shape(plan.desk)?;
+    layout.check_floor()?;
+    layout.check_spacing()?;
+    layout.check_walkway()?;
+    layout.check_chairs()?;
+
+    if layout.len() != plan.desk_count {
+        return Err(eyre!("Placed desk count {} does not match input desk count {}", layout.len(), plan.desk_count));
+    }
+
+    Ok(Verification::required(layout.len(), plan.desk_count))
+}
+
+impl Room {
+    pub fn new(width: usize, height: usize, desk_count: usize, desk: DeskShape, rules: Rules) -> Self {
+        Room { width, height, desk_count, desk, rules }
+    }
+
+    pub fn plan(&self) -> FloorPlan {
+        FloorPlan { rules: self.rules, ..FloorPlan::open(self.width, self.height, self.desk_count, self.desk) }
+    }
+}
+
+pub fn map(room: &Room, meter: &Meter) -> eyre::Result<Solution> {
+    solve(&room.plan(), meter)
+}
+
+pub fn reduce(results: Vec<String>) -> String {
+    let mut result = results.join("\r\n\r\n");
 
     result.push_str("\r\n\r\n");
 
-    Ok(result)
+    result
+}
+
+pub fn verify(input: &Room, output: &str) -> eyre::Result<Verification> {
+    verify_plan(&input.plan(), output)
+}
+
+pub fn split_output(output: &str) -> impl Iterator<Item = String> + '_ {
+    split_rooms(output)
+}
+
+#[cfg(test)]
+mod tests {
+    use itertools::Itertools;
+
+    use super::{place_desks, solve, verify_plan, Room};
+    use crate::budget::{Budget, Meter};
+    use crate::exact;
+    use crate::input::{CountedInput, Input};
+    use crate::layout::Layout;
+    use crate::levels::level5;
+    use crate::room::{DeskShape, FloorPlan, Metric, Rules, Spacing};
+
+    #[test]
+    pub fn test_blocked_cells() {
+        let input = "2\n\
+            6 4 4\n\
+            ......\n\
+            ......\n\
+            ###...\n\
+            ###...\n\
+            7 5 5\n\
+            .......\n\
+            .......\n\
+            ...#...\n\
+            .......\n\
+            .......\n";
+        let input = input.parse::<CountedInput<FloorPlan>>().unwrap();
+
+        for plan in input.subtasks() {
+            let solution = solve(plan, &Meter::default()).unwrap();
+            verify_plan(plan, &solution.output).unwrap();
+        }
+
+        let plan = input.subtasks().next().unwrap();
+        assert!(verify_plan(plan, "XX.XX.\n......\nX##...\nX##.XX\n").is_err());
+        assert!("1\n3 2 1\n...\n.#\n".parse::<CountedInput<FloorPlan>>().is_err());
+    }
+
+    #[test]
+    pub fn test_walkway() {
+        let input = "2\n\
+            2 6 2\n\
+            E.\n\
+            ..\n\
+            ..\n\
+            ..\n\
+            ..\n\
+            ..\n\
+            7 7 6\n\
+            .......\n\
+            .......\n\
+            ...#...\n\
+            .......\n\
+            .......\n\
+            .......\n\
+            ...E...\n";
+        let input = input.parse::<CountedInput<FloorPlan>>().unwrap();
+
+        for plan in input.subtasks() {
+            let solution = solve(plan, &Meter::default()).unwrap();
+            verify_plan(plan, &solution.output).unwrap();
+        }
+
+        let plan = input.subtasks().next().unwrap();
+        assert!(verify_plan(plan, "..\nXX\n..\n..\nXX\n..\n").is_err());
+        assert!(verify_plan(plan, "X.\nX.\n..\n..\n.X\n.X\n").is_err());
+    }
+
+    #[test]
+    pub fn test_chairs() {
+        let input = "3\n5 1 2\n.....\n5 1 2 chairs\n.....\n5 1 1 2x1 chairs\n.....\n";
+        let input = input.parse::<CountedInput<FloorPlan>>().unwrap();
+        let [free, seated, single] = input.subtasks().collect::<Vec<_>>()[..] else { panic!() };
+
+        verify_plan(free, "XX.XX\n").unwrap();
+        assert!(verify_plan(seated, "XX.XX\n").is_err());
+        assert!(solve(seated, &Meter::default()).is_err());
+
+        let solution = solve(single, &Meter::default()).unwrap();
+        verify_plan(single, &solution.output).unwrap();
+    }
+
+    #[test]
+    pub fn test_spacing() {
+        let input = "3\n\
+            3 3 2 manhattan:1\n...\n...\n...\n\
+            3 3 2\n...\n...\n...\n\
+            7 7 6 chebyshev:2\n.......\n.......\n.......\n.......\n.......\n.......\n.......\n";
+        let input = input.parse::<CountedInput<FloorPlan>>().unwrap();
+        let [diagonal, touching, distanced] = input.subtasks().collect::<Vec<_>>()[..] else { panic!() };
+
+        verify_plan(diagonal, "XX.\n..X\n..X\n").unwrap();
+        assert!(verify_plan(touching, "XX.\n..X\n..X\n").is_err());
+        assert!(verify_plan(distanced, "XX.XX..\n.......\nXX.XX..\n.......\n.......\n.......\n.......\n").is_err());
+
+        let solution = solve(distanced, &Meter::default()).unwrap();
+        verify_plan(distanced, &solution.output).unwrap();
+
+        let rules = |gap| Rules { spacing: Spacing::new(Metric::Chebyshev, gap), ..Rules::default() };
+        assert_eq!(exact::maximise(&Layout::new(6, 8).under(rules(0)), DeskShape::new(2, 1), &Meter::default()).len(), 24);
+        assert_eq!(exact::maximise(&Layout::new(7, 7).under(rules(2)), DeskShape::new(2, 1), &Meter::default()).len(), 6);
+    }
+
+    #[test]
+    pub fn test_touching_desks() {
+        let plan = "4 3 6 2x1 chebyshev:0".parse::<FloorPlan>().unwrap();
+        let solution = solve(&plan, &Meter::default()).unwrap();
+        assert!(solution.output.starts_with(|c: char| c.is_ascii_digit()), "{}", solution.output);
+        assert_eq!(verify_plan(&plan, &solution.output).unwrap().placed, 6);
+
+        verify_plan(&plan, "1 1 2 2\n3 3 4 4\n5 5 6 6\n").unwrap();
+        assert!(verify_plan(&plan, "XXXX\nXXXX\nXXXX\n").is_err());
+    }
+
+    #[test]
+    pub fn test_custom_desk_shapes() {
+        level5("2\n9 5 6 4\n10 10 8 3x2\n").unwrap();
+        assert_eq!("1x2".parse::<DeskShape>().unwrap(), DeskShape::new(2, 1));
+        level5("1\n5 1 2 1x2\n").unwrap();
+        level5("1\n0 0 0\n").unwrap();
+        let empty = Room { width: 0, height: 3, desk_count: 0, desk: DeskShape::new(2, 1), rules: Rules::default() };
+        assert_eq!(empty.generate_room_table_matrix_for_uneven_height().unwrap().1, 0);
+        assert_eq!(empty.generate_room_table_matrix_for_even_height().unwrap().1, 0);
+
+        for desk in [DeskShape::new(2, 1), DeskShape::new(3, 1), DeskShape::new(4, 2)] {
+            let room = Room { width: 11, height: 9, desk_count: 0, desk, rules: Rules::default() };
+            let patterns = [
+                room.generate_room_table_matrix_for_uneven_width().unwrap(),
+                room.generate_room_table_matrix_for_uneven_height().unwrap(),
+            ];
+            for (matrix, desks) in patterns {
+                let layout = Layout::parse_marks(room.width, room.height, &matrix).unwrap();
+                layout.check_shape(desk).unwrap();
+                layout.check_spacing().unwrap();
+                assert_eq!(layout.len(), desks, "{desk} desks in\n{matrix}");
+            }
+        }
+    }
+
+    #[test]
+    pub fn test_even_width() {
+        for (width, height) in (1..=14).cartesian_product(1..=15) {
+            let room = Room { width, height, desk_count: 0, desk: DeskShape::new(2, 1), rules: Rules::default() };
+            for (matrix, desks) in [
+                room.generate_room_table_matrix_for_even_width().unwrap(),
+                room.generate_room_table_matrix_for_even_height().unwrap(),
+            ] {
+                let layout = Layout::parse_marks(width, height, &matrix).unwrap();
+                layout.check_spacing().unwrap();
+                assert_eq!(layout.len(), desks, "{width}x{height}\n{matrix}");
+            }
+        }
+    }
+
+    #[test]
+    pub fn test_searches_only_when_short() {
+        let meter = Budget { nodes: Some(0), ..Budget::default() }.start();
+        let placement = place_desks(&"6 6 6".parse::<FloorPlan>().unwrap(), &meter);
+        assert!(placement.layout.len() >= 6 && placement.strategy != "exact");
+        assert!(!meter.is_exhausted());
+
+        let placement = place_desks(&"6 6 9".parse::<FloorPlan>().unwrap(), &meter);
+        assert!(placement.layout.len() < 9);
+        assert!(meter.is_exhausted());
+    }
 }
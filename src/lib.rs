@@ -0,0 +1,64 @@
+//! Solvers and verifiers for the desk placement levels of the CCC 2024 autumn contest
+//!
+//! The crate is organised like the pipeline of its command line tool, and every part of it can
+//! be used on its own:
+//!
+//! - The room model: [DeskShape], [Spacing], [Rules], [Floor] and [FloorPlan] describe a room,
+//!   a [Layout] holds the desks placed in it.
+//! - Parsers: the subtasks of every level in [levels] parse from their CCC input text via
+//!   [CountedInput], room lines parse into a [FloorPlan] and rooms with mixed desks into an
+//!   [inventory::StockedRoom].
+//! - Solvers: every level maps a subtask to a [Solution]. Desks are placed by the level5
+//!   strategies, by [tiling] with small optimal layouts, by the [exact] search, the [sat]
+//!   solver and the local [search], all limited by a [Budget] and checked against a [bound].
+//! - Verifiers: every level verifies an output for a subtask, and a [Layout] checks its shape,
+//!   floor, spacing, walkway and chairs on its own. The [editor] verifies a layout live while
+//!   its desks are placed by hand.
+//! - Renderers: a [Layout] renders as marks or desk ids, rooms also as [lp] models and DIMACS
+//!   formulas, see [sat::Encoding].
+//!
+//! On top of these, [catalog], [inverse] and [building] answer questions about many rooms, and
+//! the [server] answers solve and verify requests over HTTP, and the [repl] solves single rooms
+//! typed at a prompt.
+//!
+//! ```
+//! use ccc_2024_10::{Budget, FloorPlan};
+//! use ccc_2024_10::levels::level5;
+//!
+//! let plan = "9 7 10".parse::<FloorPlan>()?;
+//! let solution = level5::solve(&plan, &Budget::default().start())?;
+//! level5::verify_plan(&plan, &solution.output)?;
+//! # Ok::<(), eyre::Report>(())
+//! ```
+
+pub mod bound;
+pub mod budget;
+pub mod building;
+pub mod cache;
+pub mod catalog;
+pub mod editor;
+pub mod exact;
+pub mod export;
+pub mod input;
+pub mod inventory;
+pub mod inverse;
+pub mod layout;
+pub mod levels;
+pub mod lp;
+pub mod output;
+pub mod repl;
+pub mod report;
+pub mod room;
+pub mod sat;
+pub mod search;
+pub mod server;
+pub mod summary;
+pub mod tiling;
+pub mod watch;
+
+pub use bound::Bound;
+pub use budget::{Budget, Meter};
+pub use input::{CountedInput, FromLines, Input};
+pub use layout::{Desk, Layout};
+pub use output::{Solution, Verification};
+pub use room::{DeskShape, Floor, FloorPlan, Metric, Rules, Spacing};
@@ -0,0 +1,165 @@
+//! Desk placement as an integer linear program, written in the LP or MPS format
+//!
+//! Every possible desk position becomes a binary variable, groups of positions that break the
+//! spacing rule together may hold at most one desk and the objective maximises the desk count.
+//! Walkway and chair rules are not part of the model.
+
+use std::fmt::Write;
+
+use clap::ValueEnum;
+use itertools::Itertools;
+
+use crate::layout::{Desk, Layout};
+use crate::room::{DeskShape, Metric};
+
+/// How many variables are written per line, as LP readers limit the line length
+const TERMS_PER_LINE: usize = 8;
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq, ValueEnum)]
+pub enum Format {
+    /// The CPLEX LP format
+    Lp,
+    /// The free MPS format
+    Mps,
+}
+
+pub struct Model {
+    pub desks: Vec<Desk>,
+    /// Sets of desks of which at most one may be placed
+    pub constraints: Vec<Vec<usize>>,
+    /// Lines written as comments at the top of the model
+    pub comments: Vec<String>,
+}
+
+impl Model {
+    /// Models adding desks of the given shape to the desks and rules of `room`
+    ///
+    /// Under a chebyshev spacing, the desks whose boxes cover a cell form one constraint,
+    /// which is tighter than constraining every conflicting pair on its own.
+    pub fn new(room: &Layout, shape: DeskShape) -> Self {
+        let desks = room.candidates(shape);
+        let spacing = room.rules().spacing;
+
+        let constraints: Vec<Vec<usize>> = match spacing.metric {
+            Metric::Chebyshev => room.boxes(&desks)
+                .into_iter()
+                .filter(|boxes| boxes.len() > 1)
+                .unique()
+                .collect(),
+            Metric::Manhattan => room.conflicts(&desks)
+                .into_iter()
+                .map(|(a, b)| vec![a, b])
+                .collect(),
+        };
+
+        let comments = vec![
+            format!("{}x{} room with {shape} desks, spacing {spacing}", room.width, room.height),
+            format!("{} desk positions, {} conflict constraints", desks.len(), constraints.len()),
+        ];
+
+        Model { desks, constraints, comments }
+    }
+
+    fn name(desk: &Desk) -> String {
+        format!("d{}_{}_{}x{}", desk.x, desk.y, desk.width, desk.height)
+    }
+
+    fn sum<'a>(desks: impl Iterator<Item = &'a Desk>) -> String {
+        desks.chunks(TERMS_PER_LINE)
+            .into_iter()
+            .map(|line| line.map(Self::name).join(" + "))
+            .join("\n   + ")
+    }
+
+    pub fn write(&self, format: Format) -> String {
+        let mut model = String::new();
+        match format {
+            Format::Lp => self.write_lp(&mut model),
+            Format::Mps => self.write_mps(&mut model),
+        }
+        .expect("Writing to a string does not fail");
+        model
+    }
+
+    pub fn write_lp(&self, out: &mut impl Write) -> std::fmt::Result {
+        for comment in &self.comments {
+            writeln!(out, "\\ {comment}")?;
+        }
+
+        writeln!(out, "Maximize\n desks: {}", Self::sum(self.desks.iter()))?;
+        writeln!(out, "Subject To")?;
+        for (index, constraint) in self.constraints.iter().enumerate() {
+            writeln!(out, " c{index}: {} <= 1", Self::sum(constraint.iter().map(|desk| &self.desks[*desk])))?;
+        }
+        writeln!(out, "Binary")?;
+        for line in self.desks.chunks(TERMS_PER_LINE) {
+            writeln!(out, " {}", line.iter().map(Self::name).join(" "))?;
+        }
+        writeln!(out, "End")
+    }
+
+    pub fn write_mps(&self, out: &mut impl Write) -> std::fmt::Result {
+        let mut rows = vec![Vec::new(); self.desks.len()];
+        for (index, constraint) in self.constraints.iter().enumerate() {
+            for desk in constraint {
+                rows[*desk].push(index);
+            }
+        }
+
+        for comment in &self.comments {
+            writeln!(out, "* {comment}")?;
+        }
+        writeln!(out, "NAME desks\nOBJSENSE\n    MAX\nROWS\n N desks")?;
+        for index in 0..self.constraints.len() {
+            writeln!(out, " L c{index}")?;
+        }
+
+        writeln!(out, "COLUMNS\n    MARKER 'MARKER' 'INTORG'")?;
+        for (desk, rows) in self.desks.iter().zip(rows) {
+            let name = Self::name(desk);
+            writeln!(out, "    {name} desks 1")?;
+            for row in rows {
+                writeln!(out, "    {name} c{row} 1")?;
+            }
+        }
+        writeln!(out, "    MARKER 'MARKER' 'INTEND'")?;
+
+        writeln!(out, "RHS")?;
+        for index in 0..self.constraints.len() {
+            writeln!(out, "    RHS c{index} 1")?;
+        }
+        writeln!(out, "BOUNDS")?;
+        for desk in &self.desks {
+            writeln!(out, " BV BND {}", Self::name(desk))?;
+        }
+        writeln!(out, "ENDATA")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Format, Model};
+    use crate::layout::Layout;
+    use crate::room::{DeskShape, Metric, Rules, Spacing};
+
+    #[test]
+    pub fn test_model_formats() {
+        let model = Model::new(&Layout::new(4, 3), DeskShape::new(2, 1));
+        assert_eq!(model.desks.len(), 17);
+        assert!(model.constraints.iter().all(|constraint| constraint.len() > 1));
+
+        let lp = model.write(Format::Lp);
+        assert!(lp.contains("Maximize\n desks: d0_0_2x1 + d0_0_1x2"));
+        assert!(lp.contains(" c1: d0_0_2x1 + d0_0_1x2 + d1_0_2x1 + d1_0_1x2 <= 1\n"));
+        assert!(lp.ends_with("End\n"));
+
+        let mps = model.write(Format::Mps);
+        assert_eq!(mps.lines().filter(|line| line.starts_with(" L c")).count(), model.constraints.len());
+        assert_eq!(mps.lines().filter(|line| line.starts_with(" BV ")).count(), model.desks.len());
+        assert!(mps.ends_with("ENDATA\n"));
+
+        let manhattan = Rules { spacing: Spacing::new(Metric::Manhattan, 1), ..Rules::default() };
+        let model = Model::new(&Layout::new(3, 1).under(manhattan), DeskShape::new(2, 1));
+        assert_eq!(model.constraints, vec![vec![0, 1]]);
+    }
+}
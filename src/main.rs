@@ -1,29 +1,396 @@
-use color_eyre::Help;
+use std::path::PathBuf;
+use std::process::ExitCode;
+use std::time::{Duration, Instant};
+
+use clap::{ArgAction, Parser, Subcommand};
 use eyre::Context;
+use itertools::Itertools;
+use tracing_subscriber::fmt::format::FmtSpan;
+use tracing_subscriber::EnvFilter;
 
-mod input;
-mod levels;
+use ccc_2024_10::cache::Cache;
+use ccc_2024_10::editor::Editor;
+use ccc_2024_10::levels::{self, Settings};
+use ccc_2024_10::report::{self, RunReport};
+use ccc_2024_10::summary::Summary;
+use ccc_2024_10::{building, catalog, export, inventory, inverse, lp, repl, sat, server, watch};
+use ccc_2024_10::{Budget, DeskShape, FloorPlan, Input, Layout, Rules};
 
-fn main() -> eyre::Result<()> {
-    color_eyre::install()?;
+/// Solves and verifies the CCC 2024 autumn levels
+///
+/// The exit code is a bit set of the failures that occurred:
+/// 1 for general errors, 2 for parse failures, 4 for solver failures,
+/// 8 for verification failures and 16 for output write failures.
+#[derive(Parser, Debug)]
+#[command(version)]
+struct Cli {
+    /// Increase the log verbosity (-v for debug, -vv for trace)
+    #[arg(short, long, action = ArgAction::Count, global = true)]
+    verbose: u8,
+
+    /// Log filter directives overriding --verbose,
+    /// e.g. `info,[subtask{subtask=3}]=trace` to follow a single room
+    #[arg(long, env = "CCC_LOG", global = true)]
+    log: Option<String>,
+
+    /// Log the time spent in each level, task, subtask and strategy span
+    #[arg(long, global = true)]
+    timings: bool,
+
+    /// List every failing subtask with its error messages instead of counts per task
+    #[arg(short, long, global = true)]
+    expand: bool,
+
+    /// Seconds the solvers may spend on a single subtask before they settle for the best
+    /// result found so far
+    #[arg(long, global = true, default_value = "60", value_parser = parse_seconds)]
+    time_limit: Duration,
+
+    /// Search nodes the solvers may spend on a single subtask, unlimited by default
+    #[arg(long, global = true)]
+    node_limit: Option<u64>,
+
+    /// Solve every subtask again instead of reusing the solutions cached in `out/.cache`
+    #[arg(long, global = true)]
+    no_cache: bool,
+
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Solve and verify every task of every level (the default)
+    Run,
+    /// Solve levels again when their inputs change and verify hand edited outputs
+    Watch,
+    /// Bundle the verified outputs of a level and the sources into a submission archive
+    Export {
+        /// Number of the level to export
+        level: usize,
+
+        /// Directory with the outputs, defaults to `out/levelN`
+        #[arg(long)]
+        outputs: Option<PathBuf>,
+
+        /// Archive to write, defaults to `out/levelN_submission.zip`
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+    },
+    /// Write the placement formula of a room in the DIMACS format
+    Dimacs {
+        /// The room line, `width height desk_count [desk size] [rules...]`
+        #[arg(required = true, num_args = 3..)]
+        room: Vec<String>,
+
+        /// File to write, defaults to stdout
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+    },
+    /// Write the integer linear program maximising the desks in a room, for external solvers
+    Model {
+        /// The room line, `width height desk_count [desk size] [rules...]`
+        #[arg(required = true, num_args = 3..)]
+        room: Vec<String>,
+
+        #[arg(short, long, value_enum, default_value_t = lp::Format::Lp)]
+        format: lp::Format,
+
+        /// File to write, defaults to stdout
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+    },
+    /// Prove the most desks that fit into every open room up to a size, store the counts and
+    /// layouts in a catalog and mine closed-form formulas for them
+    ///
+    /// Fails if the desks counted by the even width pattern of level5 disagree with the catalog.
+    ///
+    /// The budget applies to every room. Rooms an existing catalog already proves are kept.
+    Catalog {
+        /// The largest width and height to sweep
+        #[arg(long, default_value_t = 20)]
+        max: usize,
 
-    std::fs::create_dir_all("out").wrap_err("Can not create 'out' directory")?;
+        /// The largest modulus of the width and height classes to fit formulas for
+        #[arg(long, default_value_t = 8)]
+        modulus: usize,
+
+        /// The desk size and rules, `[desk size] [rules...]`, defaults to the 2x1 desks of level5
+        desk: Vec<String>,
+
+        /// Catalog to write, defaults to `out/catalog.json`
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+    },
+    /// Find the smallest room that fits a number of desks and place them
+    ///
+    /// Writes the room line followed by the layout. The budget applies to every room tried.
+    Inverse {
+        /// The desks to fit
+        desks: usize,
+
+        /// The desk size and rules, `[desk size] [rules...]`, defaults to the 2x1 desks of level5,
+        /// e.g. `3x1 chebyshev:0` for the packed desks of level2
+        desk: Vec<String>,
+
+        #[arg(long, value_enum, default_value_t = inverse::Objective::Area)]
+        objective: inverse::Objective,
+
+        /// The preferred ratio of the width to the height
+        #[arg(long, default_value_t = 1.0)]
+        ratio: f64,
+
+        /// File to write, defaults to stdout
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+    },
+    /// Distribute a desk demand over the rooms of a building and place the desks
+    ///
+    /// The rooms are read from a file in the level5 input format, ignoring their desk counts.
+    /// Writes every room with its share of the desks and their layout.
+    Building {
+        /// The file listing the rooms
+        rooms: PathBuf,
+
+        /// The desks to distribute
+        demand: usize,
+
+        #[arg(long, value_enum, default_value_t = building::Objective::Rooms)]
+        objective: building::Objective,
+
+        /// File to write, defaults to stdout
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+    },
+    /// Place the desks of rooms that have to hold a given number of desks of several shapes
+    ///
+    /// The file lists the number of rooms, then every room as a `width height [rules...]` line
+    /// followed by its inventory, e.g. `3x1=3 2x1=5`. Writes the layouts of the rooms.
+    Inventory {
+        /// The file listing the rooms
+        rooms: PathBuf,
+
+        /// File to write, defaults to stdout
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+    },
+    /// Place the desks of a level5 room by hand in the terminal, verifying the layout on every
+    /// change, and save it into the output file
+    Edit {
+        /// The room line, `width height desk_count [desk size] [rules...]`
+        #[arg(required_unless_present = "input", conflicts_with = "input")]
+        room: Vec<String>,
+
+        /// A level5 input file to take the room from instead
+        #[arg(long)]
+        input: Option<PathBuf>,
+
+        /// The room of the input file to edit, starting at 1
+        #[arg(long, default_value_t = 1)]
+        subtask: usize,
+
+        /// Output file to save into, defaults to the output of the task of the input file or to
+        /// `out/WxH.out`
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+    },
+    /// Solve single rooms typed at a prompt, like `5 9 7 13` or `level5 9x7`
+    Repl,
+    /// Answer `POST /solve` and `POST /verify` requests with JSON bodies on a local address
+    Serve {
+        /// The address to listen on
+        #[arg(long, default_value = "127.0.0.1:8024")]
+        address: String,
+    },
+}
+
+fn parse_seconds(seconds: &str) -> Result<Duration, String> {
+    Duration::try_from_secs_f64(seconds.parse().map_err(|error| format!("{error}"))?)
+        .map_err(|error| format!("{error}"))
+}
 
+impl Cli {
+    fn budget(&self) -> Budget {
+        Budget { time: Some(self.time_limit), nodes: self.node_limit }
+    }
+
+    fn cache(&self) -> eyre::Result<Option<Cache>> {
+        if self.no_cache {
+            return Ok(None);
+        }
+        Cache::open(levels::CACHE_DIR).map(Some)
+    }
+}
+
+fn init_tracing(cli: &Cli) -> eyre::Result<()> {
+    let filter = match &cli.log {
+        Some(directives) => EnvFilter::try_new(directives)
+            .wrap_err(format!("Invalid log filter '{directives}'"))?,
+        None => EnvFilter::new(match cli.verbose {
+            0 => "info",
+            1 => "debug",
+            _ => "trace",
+        }),
+    };
+
+    let span_events = if cli.timings { FmtSpan::CLOSE } else { FmtSpan::NONE };
+
+    tracing_subscriber::fmt()
+        .with_env_filter(filter)
+        .with_span_events(span_events)
+        .with_writer(std::io::stderr)
+        .try_init()
+        .map_err(|error| eyre::eyre!(error))
+        .wrap_err("Failed to install tracing subscriber")
+}
+
+fn run(cli: &Cli) -> eyre::Result<ExitCode> {
+    std::fs::create_dir_all(levels::OUTPUT_DIR).wrap_err("Can not create 'out' directory")?;
+
+    let cache = cli.cache()?;
+    let settings = Settings { budget: cli.budget(), cache: cache.as_ref() };
+
+    let start = Instant::now();
+    let mut report = RunReport::new();
     let mut errors = Vec::new();
 
-    if let Err(mut error) = levels::run_level1() {
-        errors.append(&mut error)
+    for level in levels::LEVELS {
+        if let Err(mut error) = level.run(&mut report, &settings) {
+            errors.append(&mut error)
+        }
+    }
+
+    report.duration_ms = report::millis(start.elapsed());
+
+    let mut exit_code = report.exit_code();
+    print!("{}", Summary { report: &report, expand: cli.expand });
+
+    // Unlike the failures of the levels, the summary does not mention this one
+    if let Err(error) = report.write(format!("{}report.json", levels::OUTPUT_DIR)) {
+        exit_code |= report::GENERAL_FAILURE;
+        eprintln!("Error: {error:?}");
     }
-    if let Err(mut error) = levels::run_level2() {
-        errors.append(&mut error)
+
+    if cli.expand {
+        for error in &errors {
+            eprintln!("Error: {error:?}");
+        }
     }
 
-    if errors.len() == 0 {
-        Ok(())
-    } else {
-        let error_count = errors.len();
-        let first_error = errors.into_iter().next().unwrap();
-        Err(first_error.section(format!("And {} more errors", error_count - 1)))
+    Ok(ExitCode::from(exit_code))
+}
+
+fn write_or_print(path: Option<PathBuf>, content: String) -> eyre::Result<ExitCode> {
+    match path {
+        Some(path) => std::fs::write(&path, content).wrap_err(format!("Failed to write {}", path.display()))?,
+        None => print!("{content}"),
+    }
+    Ok(ExitCode::SUCCESS)
+}
+
+fn main() -> eyre::Result<ExitCode> {
+    color_eyre::install()?;
+    let cli = Cli::parse();
+    init_tracing(&cli)?;
+    let budget = cli.budget();
+
+    match cli.command {
+        None | Some(Command::Run) => run(&cli),
+        Some(Command::Watch) => {
+            let cache = cli.cache()?;
+            watch::watch(&Settings { budget, cache: cache.as_ref() }).map(|_| ExitCode::SUCCESS)
+        },
+        Some(Command::Export { level, outputs, output }) => {
+            let archive = export::export(levels::find(level)?, outputs, output)?;
+            println!("Exported level {level} to {}", archive.display());
+            Ok(ExitCode::SUCCESS)
+        },
+        Some(Command::Dimacs { room, output }) => {
+            let plan = room.join(" ").parse::<FloorPlan>().wrap_err("Invalid room")?;
+            let room = Layout::with_floor(&plan.floor).under(plan.rules);
+            let encoding = sat::Encoding::new(&room, plan.desk, plan.desk_count);
+
+            write_or_print(output, encoding.to_string())
+        },
+        Some(Command::Model { room, format, output }) => {
+            let plan = room.join(" ").parse::<FloorPlan>().wrap_err("Invalid room")?;
+            let room = Layout::with_floor(&plan.floor).under(plan.rules);
+            let mut model = lp::Model::new(&room, plan.desk);
+
+            let placement = levels::level5::place_desks(&plan, &budget.start());
+            model.comments.push(format!(
+                "level5 places {} desks with the {} strategy",
+                placement.layout.len(), placement.strategy,
+            ));
+
+            write_or_print(output, model.write(format))
+        },
+        Some(Command::Catalog { max, modulus, desk, output }) => {
+            let (desk, rules) = Rules::parse_columns(desk.iter().map(String::as_str), DeskShape::new(2, 1))?;
+            let path = output.unwrap_or_else(|| PathBuf::from(format!("{}catalog.json", levels::OUTPUT_DIR)));
+            let known = path.exists().then(|| catalog::Catalog::read(&path)).transpose()?;
+
+            let mut catalog = catalog::sweep(max, desk, rules, budget, known.as_ref());
+            catalog.formulas = catalog::mine(&catalog.entries, modulus);
+            catalog.write(&path)?;
+
+            let unproven = catalog.entries.iter().filter(|entry| !entry.optimal).count();
+            println!("Catalogued {} rooms in {}, {unproven} not proven optimal", catalog.entries.len(), path.display());
+            for formula in &catalog.formulas {
+                println!("{formula}");
+            }
+            for entry in catalog.shortfalls() {
+                println!(
+                    "The {} strategy places {} of the {} desks that fit into {}x{}",
+                    entry.strategy, entry.strategy_desks, entry.desks, entry.width, entry.height,
+                );
+            }
+            let miscounts = catalog.miscounts();
+            for miscount in &miscounts {
+                eprintln!("{miscount}");
+            }
+            Ok(if miscounts.is_empty() { ExitCode::SUCCESS } else { ExitCode::from(report::GENERAL_FAILURE) })
+        },
+        Some(Command::Inverse { desks, desk, objective, ratio, output }) => {
+            let (desk, rules) = Rules::parse_columns(desk.iter().map(String::as_str), DeskShape::new(2, 1))?;
+            let request = inverse::Request { desks, desk, rules, objective, ratio };
+            let room = request.solve(budget)?;
+
+            tracing::info!("The {}x{} room fits {desks} desks with the {} strategy", room.width, room.height, room.strategy);
+            if !room.undecided.is_empty() {
+                let rooms = room.undecided.iter().map(|(width, height)| format!("{width}x{height}")).join(", ");
+                tracing::warn!("Smaller rooms might fit the desks too, the solvers could not decide {rooms}");
+            }
+
+            let line = format!("{} {} {desks} {desk} {rules}", room.width, room.height);
+            write_or_print(output, format!("{line}\n{}\n", room.layout.render()))
+        },
+        Some(Command::Building { rooms, demand, objective, output }) => {
+            let input = std::fs::read_to_string(&rooms).wrap_err(format!("Failed to read {}", rooms.display()))?;
+            let input = input.parse::<levels::level5::Input>().wrap_err("Invalid rooms")?;
+            let plans = input.subtasks().map(levels::level5::Room::plan).collect();
+            let allocations = building::allocate(plans, demand, objective, budget)?;
+
+            let used = allocations.iter().filter(|allocation| allocation.desks > 0).count();
+            tracing::info!("Placed {demand} desks in {used} of {} rooms", allocations.len());
+            write_or_print(output, format!("{}\n", allocations.iter().join("\n\n")))
+        },
+        Some(Command::Inventory { rooms, output }) => {
+            let input = std::fs::read_to_string(&rooms).wrap_err(format!("Failed to read {}", rooms.display()))?;
+            write_or_print(output, inventory::solve_input(&input, budget)?)
+        },
+        Some(Command::Edit { ref room, ref input, subtask, ref output }) => {
+            let editor = Editor::load(room, input.as_deref(), subtask, output.clone())?;
+            editor.run().map(|_| ExitCode::SUCCESS)
+        },
+        Some(Command::Repl) => {
+            let cache = cli.cache()?;
+            repl::repl(&Settings { budget, cache: cache.as_ref() }).map(|_| ExitCode::SUCCESS)
+        },
+        Some(Command::Serve { ref address }) => {
+            let cache = cli.cache()?;
+            server::serve(address, &Settings { budget, cache: cache.as_ref() }).map(|_| ExitCode::SUCCESS)
+        },
     }
 }
 
@@ -0,0 +1,51 @@
+//! What solving and verifying a single subtask results in
+
+use crate::bound::Bound;
+use crate::search::Improvement;
+
+/// The result of mapping a single subtask
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Solution {
+    pub output: String,
+    pub strategy: Option<&'static str>,
+    pub improvement: Option<Improvement>,
+    /// The most desks that can fit, if the level knows it
+    pub bound: Option<Bound>,
+}
+
+impl Solution {
+    pub fn with_strategy(output: String, strategy: &'static str) -> Self {
+        Solution { output, strategy: Some(strategy), improvement: None, bound: None }
+    }
+
+    pub fn with_improvement(self, improvement: Option<Improvement>) -> Self {
+        Solution { improvement, ..self }
+    }
+
+    pub fn with_bound(self, bound: Bound) -> Self {
+        Solution { bound: Some(bound), ..self }
+    }
+}
+
+impl From<String> for Solution {
+    fn from(output: String) -> Self {
+        Solution { output, strategy: None, improvement: None, bound: None }
+    }
+}
+
+/// What a verifier found out about a valid subtask output
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+pub struct Verification {
+    pub placed: usize,
+    pub required: Option<usize>,
+}
+
+impl Verification {
+    pub fn placed(placed: usize) -> Self {
+        Verification { placed, required: None }
+    }
+
+    pub fn required(placed: usize, required: usize) -> Self {
+        Verification { placed, required: Some(required) }
+    }
+}
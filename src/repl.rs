@@ -0,0 +1,178 @@
+//! An interactive prompt that solves single rooms, for exploring edge cases without input files
+//!
+//! A query names a level and a room line, like `5 9 7 13` or `level5 9x7 chairs`. The room is
+//! solved as a one-room input of the level and answered with the layout, the desks, the strategy
+//! and the bound. Without a desk count, a level5 room is filled with as many desks as fit, as in
+//! the [catalog].
+
+use eyre::WrapErr;
+use itertools::Itertools;
+use rustyline::error::ReadlineError;
+
+use crate::catalog;
+use crate::levels::{self, Settings};
+use crate::report::{self, TaskReport};
+use crate::room::{DeskShape, Rules};
+
+const HELP: &str = "\
+Queries are a level and a room line, e.g. `5 9 7 13`, `level5 9x7 6 3x1 manhattan:2` or
+`level1 6 5`. Without a desk count, level5 rooms are filled with as many desks as fit.
+`help` shows this text, `quit` or Ctrl-D leaves.";
+
+/// A level and the room line to solve for it
+#[derive(Debug, Eq, PartialEq)]
+pub struct Query {
+    pub level: usize,
+    pub room: String,
+}
+
+impl Query {
+    /// Parses `[level]N room`, where the room may start with `WxH` instead of `W H`
+    pub fn parse(line: &str) -> eyre::Result<Self> {
+        let mut values = line.split_whitespace();
+        let level = values.next().ok_or(eyre::eyre!("Missing level"))?;
+        let level = level.strip_prefix("level").unwrap_or(level)
+            .parse()
+            .map_err(|_| eyre::eyre!("'{level}' is not a level, write e.g. `5` or `level5`"))?;
+
+        let mut room = values.map(str::to_string).collect::<Vec<_>>();
+        if let Some((width, height)) = room.first().and_then(|size| size.split_once('x')).map(|(w, h)| (w.to_string(), h.to_string())) {
+            room.splice(0..1, [width, height]);
+        }
+        if room.is_empty() {
+            return Err(eyre::eyre!("Missing room after level {level}"));
+        }
+
+        Ok(Query { level, room: room.join(" ") })
+    }
+
+    /// Whether the query asks for the most desks in a level5 room instead of a given count
+    fn fills(&self) -> bool {
+        let mut values = self.room.split_whitespace().skip(2);
+        self.level == 5 && values.next().is_none_or(|count| count.parse::<usize>().is_err())
+    }
+
+    /// Solves the room and describes the result
+    pub fn answer(&self, settings: &Settings) -> eyre::Result<String> {
+        if self.fills() {
+            return self.fill(settings);
+        }
+
+        let level = levels::find(self.level)?;
+        let mut report = TaskReport::new(1);
+        let output = (level.solve)(&format!("1\n{}\n", self.room), settings, &mut report)
+            .map_err(|errors| match report.subtasks.first() {
+                // The pipeline has already logged why the subtask failed
+                Some(subtask) => eyre::eyre!("level{} {}: {}", self.level, self.room, subtask.status),
+                None => eyre::eyre!(errors.iter().map(|error| format!("{error:#}")).join("\n")),
+            })?;
+
+        let mut summary = vec![format!("level{} {}", self.level, self.room)];
+        if let Some(subtask) = report.subtasks.first() {
+            if let Some(desks) = subtask.desks {
+                summary.push(match desks.required {
+                    Some(required) => format!("{} of {required} desks", desks.placed),
+                    None => format!("{} desks", desks.placed),
+                });
+            }
+            if let Some(strategy) = subtask.strategy {
+                summary.push(format!("{strategy} strategy"));
+            }
+            if let Some(bound) = subtask.bound {
+                summary.push(format!("bound {bound}"));
+            }
+            summary.push(format!("{:.1} ms", subtask.duration_ms));
+        }
+
+        Ok(format!("{}\n{}", output.replace("\r\n", "\n").trim_end(), summary.join(", ")))
+    }
+
+    /// [Query::answer], turning a panicking solver into an error so that the session goes on
+    pub fn answer_caught(&self, settings: &Settings) -> eyre::Result<String> {
+        report::catch_panic(|| self.answer(settings)).wrap_err(format!("level{} {}", self.level, self.room))?
+    }
+
+    fn fill(&self, settings: &Settings) -> eyre::Result<String> {
+        let mut values = self.room.split_whitespace();
+        let mut size = || values.next().ok_or(eyre::eyre!("Missing height")).and_then(|value| {
+            value.parse::<usize>().map_err(|_| eyre::eyre!("'{value}' is not a size"))
+        });
+        let (width, height) = (size()?, size()?);
+        let (desk, rules) = Rules::parse_columns(values, DeskShape::new(2, 1))?;
+
+        let entry = catalog::solve(width, height, desk, rules, settings.budget);
+        let proven = if entry.optimal { "proven optimal" } else { "not proven optimal" };
+        Ok(format!(
+            "{}\nlevel5 {}, {} desks {proven}, the {} strategy places {}",
+            entry.layout.replace("\r\n", "\n"), self.room, entry.desks, entry.strategy, entry.strategy_desks,
+        ))
+    }
+}
+
+/// Answers queries until the input ends, keeping the history in `out/.repl_history`
+pub fn repl(settings: &Settings) -> eyre::Result<()> {
+    let history = format!("{}.repl_history", levels::OUTPUT_DIR);
+    let mut editor = rustyline::DefaultEditor::new()?;
+    // A missing history is expected on the first start
+    let _ = editor.load_history(&history);
+    println!("{HELP}");
+
+    loop {
+        let line = match editor.readline("> ") {
+            Ok(line) => line,
+            Err(ReadlineError::Interrupted | ReadlineError::Eof) => break,
+            Err(error) => return Err(error.into()),
+        };
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+        editor.add_history_entry(line)?;
+
+        match line {
+            "help" => println!("{HELP}"),
+            "quit" | "exit" => break,
+            _ => match Query::parse(line).and_then(|query| query.answer_caught(settings)) {
+                Ok(answer) => println!("{answer}"),
+                Err(error) => eprintln!("{error:#}"),
+            },
+        }
+    }
+
+    if let Err(error) = editor.save_history(&history) {
+        tracing::warn!("Failed to save the history to '{history}': {error}");
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Query;
+    use crate::levels::Settings;
+
+    #[test]
+    pub fn test_queries() {
+        assert_eq!(Query::parse("level5 9x7 13").unwrap(), Query { level: 5, room: "9 7 13".to_string() });
+        assert_eq!(Query::parse("2 6 5 10 3x1").unwrap(), Query { level: 2, room: "6 5 10 3x1".to_string() });
+        assert!(Query::parse("levelfive 9 7").is_err());
+        assert!(Query::parse("5").is_err());
+
+        let settings = Settings::default();
+        let answer = Query::parse("5 9 7 13").unwrap().answer(&settings).unwrap();
+        assert!(answer.contains("level5 9 7 13, 13 of 13 desks, uneven_width strategy, bound 13 (counting bound)"), "{answer}");
+        assert_eq!(answer.lines().count(), 8);
+
+        let filled = Query::parse("level5 9x7").unwrap().answer(&settings).unwrap();
+        assert!(filled.ends_with("13 desks proven optimal, the uneven_width strategy places 13"), "{filled}");
+
+        let touching = Query::parse("5 4x3 6 2x1 chebyshev:0").unwrap().answer(&settings).unwrap();
+        assert!(touching.starts_with("1 1 "), "{touching}");
+        let error = Query::parse("5 9 7 13 2x1 chebyshev:2").unwrap().answer(&settings).unwrap_err();
+        assert_eq!(error.to_string(), "level5 9 7 13 2x1 chebyshev:2: solve failed");
+
+        assert!(Query::parse("5 4 1 3").unwrap().answer(&settings).is_err());
+        for query in ["5 1 2 1x2", "5 0 0 0", "level5 0x0"] {
+            Query::parse(query).unwrap().answer_caught(&settings).unwrap();
+        }
+    }
+}
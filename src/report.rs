@@ -0,0 +1,346 @@
+//! The machine readable report of a run, written to `out/report.json`
+
+use std::fmt::{Debug, Display, Formatter};
+use std::iter;
+use std::panic::AssertUnwindSafe;
+use std::path::Path;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use eyre::WrapErr;
+use serde::Serialize;
+
+use crate::bound::Bound;
+use crate::output::Verification;
+use crate::search::Improvement;
+
+/// Exit code bit for failures outside of the levels themselves, e.g. an unwritable report
+pub const GENERAL_FAILURE: u8 = 1;
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Status {
+    Passed,
+    ParseFailed,
+    SolveFailed,
+    VerifyFailed,
+    WriteFailed,
+}
+
+impl Status {
+    pub fn is_passed(&self) -> bool {
+        *self == Status::Passed
+    }
+
+    /// The bit this status sets in the process exit code, so that callers can tell
+    /// parse, solver and verification failures apart even when several occur in one run
+    pub fn exit_code(&self) -> u8 {
+        match self {
+            Status::Passed => 0,
+            Status::ParseFailed => 2,
+            Status::SolveFailed => 4,
+            Status::VerifyFailed => 8,
+            Status::WriteFailed => 16,
+        }
+    }
+}
+
+impl Display for Status {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Status::Passed => "passed",
+            Status::ParseFailed => "parse failed",
+            Status::SolveFailed => "solve failed",
+            Status::VerifyFailed => "verify failed",
+            Status::WriteFailed => "write failed",
+        })
+    }
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct RunReport {
+    pub started_at: u64,
+    pub duration_ms: f64,
+    pub levels: Vec<LevelReport>,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct LevelReport {
+    pub level: usize,
+    pub name: &'static str,
+    pub status: Status,
+    pub duration_ms: f64,
+    pub messages: Vec<String>,
+    pub tasks: Vec<TaskReport>,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct TaskReport {
+    pub task: usize,
+    pub status: Status,
+    pub duration_ms: f64,
+    pub output: Option<String>,
+    pub messages: Vec<String>,
+    pub subtasks: Vec<SubtaskReport>,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct SubtaskReport {
+    pub subtask: usize,
+    pub input: String,
+    pub status: Status,
+    pub duration_ms: f64,
+    pub strategy: Option<&'static str>,
+    /// Set if a local search added desks to the layout the strategy started from
+    pub improvement: Option<Improvement>,
+    /// Set if the budget ran out, so that the result is the best one found until then
+    pub not_proven_optimal: bool,
+    pub desks: Option<DeskCount>,
+    /// The most desks that can fit, to compare the placed desks with
+    pub bound: Option<Bound>,
+    pub messages: Vec<String>,
+}
+
+#[derive(Copy, Clone, Debug, Serialize)]
+pub struct DeskCount {
+    pub placed: usize,
+    pub required: Option<usize>,
+}
+
+impl From<Verification> for DeskCount {
+    fn from(verification: Verification) -> Self {
+        DeskCount {
+            placed: verification.placed,
+            required: verification.required,
+        }
+    }
+}
+
+/// Flattens the chain of an error into one message per cause
+pub fn messages(error: &eyre::Report) -> Vec<String> {
+    error.chain().map(ToString::to_string).collect()
+}
+
+/// Runs `f` and turns a panic into an error with the panic message, so that one failing request
+/// does not end a service that answers many
+pub fn catch_panic<T>(f: impl FnOnce() -> T) -> eyre::Result<T> {
+    std::panic::catch_unwind(AssertUnwindSafe(f)).map_err(|panic| {
+        let message = panic.downcast_ref::<&str>().map(|message| message.to_string())
+            .or_else(|| panic.downcast_ref::<String>().cloned())
+            .unwrap_or_else(|| "unknown cause".to_string());
+        eyre::eyre!("The solver panicked: {message}")
+    })
+}
+
+pub fn millis(duration: Duration) -> f64 {
+    duration.as_secs_f64() * 1000.0
+}
+
+impl Default for RunReport {
+    fn default() -> Self {
+        RunReport::new()
+    }
+}
+
+impl RunReport {
+    pub fn new() -> Self {
+        let started_at = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|duration| duration.as_secs())
+            .unwrap_or_default();
+
+        RunReport { started_at, duration_ms: 0.0, levels: Vec::new() }
+    }
+
+    pub fn exit_code(&self) -> u8 {
+        self.levels.iter()
+            .flat_map(|level| {
+                let subtasks = level.tasks.iter()
+                    .flat_map(|task| task.subtasks.iter().map(|subtask| subtask.status));
+                let tasks = level.tasks.iter().map(|task| task.status);
+
+                iter::once(level.status).chain(tasks).chain(subtasks)
+            })
+            .fold(0, |code, status| code | status.exit_code())
+    }
+
+    pub fn write(&self, path: impl AsRef<Path>) -> eyre::Result<()> {
+        let path = path.as_ref();
+        let json = serde_json::to_string_pretty(self).wrap_err("Cannot serialize run report")?;
+        std::fs::write(path, json).wrap_err(format!("Cannot write run report to '{}'", path.display()))
+    }
+}
+
+impl LevelReport {
+    pub fn new(level: usize, name: &'static str) -> Self {
+        LevelReport { level, name, status: Status::Passed, duration_ms: 0.0, messages: Vec::new(), tasks: Vec::new() }
+    }
+
+    /// Records a level wide failure and hands the error back for further propagation
+    pub fn fail(&mut self, status: Status, error: eyre::Report) -> eyre::Report {
+        if self.status.is_passed() {
+            self.status = status;
+        }
+        self.messages.append(&mut messages(&error));
+        error
+    }
+
+    pub fn push(&mut self, task: TaskReport) {
+        if !task.status.is_passed() && self.status.is_passed() {
+            self.status = task.status;
+        }
+        self.tasks.push(task);
+    }
+}
+
+impl TaskReport {
+    pub fn new(task: usize) -> Self {
+        TaskReport {
+            task,
+            status: Status::Passed,
+            duration_ms: 0.0,
+            output: None,
+            messages: Vec::new(),
+            subtasks: Vec::new(),
+        }
+    }
+
+    /// Records a task wide failure and hands the error back for further propagation
+    pub fn fail(&mut self, status: Status, error: eyre::Report) -> eyre::Report {
+        if self.status.is_passed() {
+            self.status = status;
+        }
+        self.messages.append(&mut messages(&error));
+        error
+    }
+
+    pub fn push(&mut self, subtask: SubtaskReport) {
+        if !subtask.status.is_passed() && self.status.is_passed() {
+            self.status = subtask.status;
+        }
+        self.subtasks.push(subtask);
+    }
+}
+
+impl SubtaskReport {
+    pub fn new(subtask: usize, input: &impl Debug) -> Self {
+        SubtaskReport {
+            subtask,
+            input: format!("{input:?}"),
+            status: Status::Passed,
+            duration_ms: 0.0,
+            strategy: None,
+            improvement: None,
+            not_proven_optimal: false,
+            desks: None,
+            bound: None,
+            messages: Vec::new(),
+        }
+    }
+
+    /// Flags the result as not proven optimal after the search for it was cut short, unless it
+    /// already places as many desks as the bound allows
+    pub fn cut_short(&mut self, nodes: u64, placed: usize, bound: Option<Bound>) {
+        if bound.is_none_or(|bound| placed < bound.desks) {
+            tracing::warn!(nodes, "Budget ran out, the result is not proven optimal");
+            self.not_proven_optimal = true;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::{json, Value};
+
+    use super::{catch_panic, LevelReport, RunReport, Status, SubtaskReport, TaskReport};
+    use crate::bound::Bound;
+    use crate::budget::Budget;
+    use crate::levels::{self, Settings};
+
+    #[test]
+    pub fn test_schema() {
+        let level = levels::find(5).unwrap();
+        let mut task = TaskReport::new(1);
+        assert!((level.solve)("2\n9 7 10\n4 1 3\n", &Settings::default(), &mut task).is_err());
+        let mut report = LevelReport::new(level.number, level.name);
+        report.push(task);
+        let run = RunReport { levels: vec![report], ..RunReport::new() };
+
+        let json = serde_json::to_value(&run).unwrap();
+        let keys = |value: &Value| value.as_object().unwrap().keys().cloned().collect::<Vec<_>>();
+        assert_eq!(keys(&json), ["duration_ms", "levels", "started_at"]);
+        let level = &json["levels"][0];
+        assert_eq!(keys(level), ["duration_ms", "level", "messages", "name", "status", "tasks"]);
+        assert_eq!((&level["level"], &level["name"], &level["status"]), (&json!(5), &json!("level5"), &json!("solve_failed")));
+
+        let task = &level["tasks"][0];
+        assert_eq!(keys(task), ["duration_ms", "messages", "output", "status", "subtasks", "task"]);
+        let [passed, failed] = task["subtasks"].as_array().unwrap().as_slice() else { panic!("{task}") };
+        assert_eq!(keys(passed), [
+            "bound", "desks", "duration_ms", "improvement", "input", "messages", "not_proven_optimal", "status", "strategy", "subtask",
+        ]);
+        assert_eq!((&passed["status"], &passed["desks"]), (&json!("passed"), &json!({ "placed": 10, "required": 10 })));
+        assert!(passed["strategy"].is_string() && passed["bound"]["desks"].as_u64() >= Some(10), "{passed}");
+
+        assert_eq!((&failed["subtask"], &failed["status"]), (&json!(2), &json!("solve_failed")));
+        assert_eq!(failed["desks"], Value::Null);
+        assert!(failed["messages"][0].as_str().unwrap().contains("has failed"), "{failed}");
+        assert_eq!(run.levels[0].status, Status::SolveFailed);
+    }
+
+    #[test]
+    pub fn test_not_proven_optimal() {
+        let bound = Some(Bound { desks: 13, method: "counting" });
+        let mut subtask = SubtaskReport::new(1, &"9 7 13");
+        subtask.cut_short(3000, 13, bound);
+        assert!(!subtask.not_proven_optimal);
+        subtask.cut_short(3000, 12, bound);
+        assert!(subtask.not_proven_optimal);
+        let mut unbounded = SubtaskReport::new(2, &"9 7 13");
+        unbounded.cut_short(3000, 13, None);
+        assert!(unbounded.not_proven_optimal);
+
+        let settings = Settings { budget: Budget { nodes: Some(3000), ..Budget::default() }, cache: None };
+        let mut task = TaskReport::new(1);
+        (levels::find(5).unwrap().solve)("2\n9 7 13\n6 6 8\n", &settings, &mut task).unwrap();
+        assert!(task.subtasks.iter().all(|subtask| !subtask.not_proven_optimal));
+    }
+
+    #[test]
+    pub fn test_catch_panic() {
+        assert_eq!(catch_panic(|| 1).unwrap(), 1);
+        let error = catch_panic(|| -> usize { panic!("attempt to subtract with overflow") }).unwrap_err();
+        assert_eq!(error.to_string(), "The solver panicked: attempt to subtract with overflow");
+        let index = 3;
+        let error = catch_panic(|| [1, 2][index]).unwrap_err();
+        assert!(error.to_string().contains("index out of bounds"), "{error}");
+    }
+
+    #[test]
+    pub fn test_exit_code() {
+        let level = levels::find(5).unwrap();
+        let settings = Settings::default();
+        let run = |tasks: Vec<TaskReport>| {
+            let mut report = LevelReport::new(level.number, level.name);
+            tasks.into_iter().for_each(|task| report.push(task));
+            RunReport { levels: vec![report], ..RunReport::new() }
+        };
+
+        let mut passed = TaskReport::new(1);
+        (level.solve)("1\n9 7 10\n", &settings, &mut passed).unwrap();
+        let mut parse = TaskReport::new(2);
+        (level.solve)("1\nnine 7 10\n", &settings, &mut parse).unwrap_err();
+        let mut solve = TaskReport::new(3);
+        (level.solve)("1\n4 1 3\n", &settings, &mut solve).unwrap_err();
+        let mut verify = TaskReport::new(4);
+        (level.verify)("1\n4 1 2\n", "XX..\n", &mut verify).unwrap_err();
+        let mut write = TaskReport::new(5);
+        let _ = write.fail(Status::WriteFailed, eyre::eyre!("Cannot write"));
+
+        assert_eq!(run(vec![passed.clone()]).exit_code(), 0);
+        assert_eq!(run(vec![passed.clone(), parse.clone()]).exit_code(), 2);
+        assert_eq!(run(vec![solve.clone()]).exit_code(), 4);
+        assert_eq!(run(vec![verify.clone()]).exit_code(), 8);
+        assert_eq!(run(vec![write.clone()]).exit_code(), 16);
+        assert_eq!(run(vec![passed, parse, solve, verify, write]).exit_code(), 2 | 4 | 8 | 16);
+    }
+}
@@ -0,0 +1,360 @@
+//! The shape of desks, the floor of a room and the rules desks are placed under
+
+use std::fmt::{Debug, Display, Formatter};
+use std::str::{FromStr, Lines};
+
+use eyre::{eyre, WrapErr};
+
+use crate::input::{FromLines, Subtask};
+
+/// The footprint of a desk, `length` cells long and `thickness` cells wide
+///
+/// Desks can be placed horizontally (`length` wide) or vertically (`length` tall), so a desk is
+/// never thicker than it is long: a `1x2` desk is the same as a `2x1` desk.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub struct DeskShape {
+    pub length: usize,
+    pub thickness: usize,
+}
+
+impl DeskShape {
+    /// Swaps the sides if needed, so that `length >= thickness`
+    pub const fn new(length: usize, thickness: usize) -> Self {
+        if length < thickness {
+            DeskShape { length: thickness, thickness: length }
+        } else {
+            DeskShape { length, thickness }
+        }
+    }
+
+    /// Width and height of a horizontally placed desk
+    pub fn horizontal(&self) -> (usize, usize) {
+        (self.length, self.thickness)
+    }
+
+    /// Width and height of a vertically placed desk
+    pub fn vertical(&self) -> (usize, usize) {
+        (self.thickness, self.length)
+    }
+
+    pub fn orientations(&self) -> Vec<(usize, usize)> {
+        if self.length == self.thickness {
+            vec![self.horizontal()]
+        } else {
+            vec![self.horizontal(), self.vertical()]
+        }
+    }
+
+    pub fn fits(&self, width: usize, height: usize) -> bool {
+        (width, height) == self.horizontal() || (width, height) == self.vertical()
+    }
+
+    /// Parses the optional desk size column that may follow the regular columns of a room line
+    pub fn parse_column(value: Option<&str>, default: DeskShape) -> eyre::Result<DeskShape> {
+        match value {
+            Some(value) => value.parse().wrap_err("Invalid desk size"),
+            None => Ok(default),
+        }
+    }
+}
+
+impl FromStr for DeskShape {
+    type Err = eyre::Report;
+
+    /// Parses `k` as a `k`x1 desk or `kxt` as a desk `k` long and `t` thick, see [DeskShape::new]
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (length, thickness) = match s.split_once('x') {
+            Some((length, thickness)) => (length, thickness),
+            None => (s, "1"),
+        };
+
+        let shape = DeskShape::new(
+            length.parse().wrap_err("Invalid desk length")?,
+            thickness.parse().wrap_err("Invalid desk thickness")?,
+        );
+
+        if shape.length == 0 || shape.thickness == 0 {
+            return Err(eyre!("Desk size {s} is empty"));
+        }
+
+        Ok(shape)
+    }
+}
+
+impl Display for DeskShape {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}x{}", self.length, self.thickness)
+    }
+}
+
+/// Marks the cells of a room desks can not be placed on, e.g. pillars, door swings or the
+/// outside of an L-shaped office
+///
+/// A floor with an entry requires a walkway: every free cell must be reachable from the entry
+/// and every desk must border a free cell.
+#[derive(Clone, Eq, PartialEq, Hash)]
+pub struct Floor {
+    pub width: usize,
+    pub height: usize,
+    blocked: Vec<bool>,
+    entry: Option<(usize, usize)>,
+}
+
+impl Floor {
+    pub fn open(width: usize, height: usize) -> Self {
+        Floor { width, height, blocked: vec![false; width * height], entry: None }
+    }
+
+    /// Reads `height` lines of `width` cells, `#` for blocked, `.` for free cells and `E` for
+    /// the free cell at the entry
+    pub fn from_lines(lines: &mut Lines, width: usize, height: usize) -> eyre::Result<Self> {
+        let mut blocked = Vec::with_capacity(width * height);
+        let mut entry = None;
+
+        for row in 0..height {
+            let line = lines.next().ok_or(eyre!("Missing floor row {}", row + 1))?.trim_end();
+            if line.chars().count() != width {
+                return Err(eyre!("Floor row {} has {} cells for a {width} wide room", row + 1, line.chars().count()));
+            }
+
+            for (column, cell) in line.chars().enumerate() {
+                blocked.push(match cell {
+                    '#' => true,
+                    '.' => false,
+                    'E' if entry.is_none() => {
+                        entry = Some((column, row));
+                        false
+                    }
+                    'E' => return Err(eyre!("Second entry in floor row {}", row + 1)),
+                    other => return Err(eyre!("Invalid floor cell '{other}' in row {}", row + 1)),
+                });
+            }
+        }
+
+        Ok(Floor { width, height, blocked, entry })
+    }
+
+    pub fn entry(&self) -> Option<(usize, usize)> {
+        self.entry
+    }
+
+    /// Whether the floor is unconstrained
+    pub fn is_open(&self) -> bool {
+        self.entry.is_none() && self.blocked_count() == 0
+    }
+
+    pub fn is_blocked(&self, x: usize, y: usize) -> bool {
+        self.blocked[y * self.width + x]
+    }
+
+    pub fn blocked_count(&self) -> usize {
+        self.blocked.iter().filter(|blocked| **blocked).count()
+    }
+
+    /// The blocked cells of a `width` x `height` part of the floor starting at `x`, `y`,
+    /// without the entry
+    pub fn window(&self, x: usize, y: usize, width: usize, height: usize) -> Floor {
+        let blocked = (y..y + height)
+            .flat_map(|row| (x..x + width).map(move |column| (column, row)))
+            .map(|(column, row)| self.is_blocked(column, row))
+            .collect();
+        Floor { width, height, blocked, entry: None }
+    }
+
+    pub fn transposed(&self) -> Floor {
+        let mut blocked = vec![false; self.blocked.len()];
+        transpose::transpose(&self.blocked, &mut blocked, self.width, self.height);
+        Floor { width: self.height, height: self.width, blocked, entry: self.entry.map(|(x, y)| (y, x)) }
+    }
+}
+
+impl Debug for Floor {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Floor({}x{}, {} blocked", self.width, self.height, self.blocked_count())?;
+        if let Some((x, y)) = self.entry {
+            write!(f, ", entry at {x},{y}")?;
+        }
+        write!(f, ")")
+    }
+}
+
+/// How the distance between two cells is measured
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub enum Metric {
+    /// Steps along edges, so diagonal neighbours are two apart
+    Manhattan,
+    /// Steps along edges or corners, so diagonal neighbours are one apart
+    Chebyshev,
+}
+
+impl Metric {
+    pub fn distance(&self, dx: usize, dy: usize) -> usize {
+        match self {
+            Metric::Manhattan => dx + dy,
+            Metric::Chebyshev => dx.max(dy),
+        }
+    }
+}
+
+impl Display for Metric {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Metric::Manhattan => write!(f, "manhattan"),
+            Metric::Chebyshev => write!(f, "chebyshev"),
+        }
+    }
+}
+
+/// The minimum number of free cells between two desks
+///
+/// Cells of different desks have to be more than `gap` apart, so a gap of 0 lets desks touch
+/// and the default, a chebyshev gap of 1, forbids touching along edges and corners like in
+/// level4 and level5.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub struct Spacing {
+    pub metric: Metric,
+    pub gap: usize,
+}
+
+impl Spacing {
+    pub const fn new(metric: Metric, gap: usize) -> Self {
+        Spacing { metric, gap }
+    }
+
+    /// Whether desks at the given distances along both axes keep this spacing
+    pub fn keeps(&self, dx: usize, dy: usize) -> bool {
+        self.metric.distance(dx, dy) > self.gap
+    }
+}
+
+impl Default for Spacing {
+    fn default() -> Self {
+        Spacing::new(Metric::Chebyshev, 1)
+    }
+}
+
+impl FromStr for Spacing {
+    type Err = eyre::Report;
+
+    /// Parses `metric:gap`, e.g. `manhattan:2`
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (metric, gap) = s.split_once(':').ok_or(eyre!("Spacing {s} is not of the form metric:gap"))?;
+        let metric = match metric {
+            "manhattan" => Metric::Manhattan,
+            "chebyshev" => Metric::Chebyshev,
+            other => return Err(eyre!("Unknown distance metric '{other}'")),
+        };
+
+        Ok(Spacing::new(metric, gap.parse().wrap_err("Invalid spacing gap")?))
+    }
+}
+
+impl Display for Spacing {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}:{}", self.metric, self.gap)
+    }
+}
+
+/// Placement rules on top of the shape of the desks
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq, Hash)]
+pub struct Rules {
+    pub spacing: Spacing,
+    /// Every desk needs its own free chair cell next to one of its edges
+    pub chairs: bool,
+}
+
+impl Rules {
+    /// Enables the rule named by a keyword of a room line, `chairs` or a [Spacing]
+    pub fn enable(&mut self, keyword: &str) -> eyre::Result<()> {
+        match keyword {
+            "chairs" => self.chairs = true,
+            spacing if spacing.contains(':') => self.spacing = spacing.parse()?,
+            other => return Err(eyre!("Unknown rule '{other}'")),
+        }
+        Ok(())
+    }
+
+    /// Parses the optional desk size column and the rule keywords that end a room line
+    pub fn parse_columns<'a>(values: impl Iterator<Item = &'a str>, default: DeskShape) -> eyre::Result<(DeskShape, Rules)> {
+        let mut values = values.peekable();
+        let desk = match values.peek() {
+            Some(value) if value.starts_with(|c: char| c.is_ascii_digit()) => values.next(),
+            _ => None,
+        };
+        let mut rules = Rules::default();
+        for keyword in values {
+            rules.enable(keyword)?;
+        }
+
+        Ok((DeskShape::parse_column(desk, default)?, rules))
+    }
+}
+
+impl Display for Rules {
+    /// Writes the keywords that enable the rules
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.spacing)?;
+        if self.chairs {
+            write!(f, " chairs")?;
+        }
+        Ok(())
+    }
+}
+
+/// A room with blocked cells, given by a `width height desk_count [desk size] [rules...]` line
+/// followed by the rows of its [Floor]
+///
+/// Without a desk size the 2x1 desks of level5 are used. Rules are given by keywords, see
+/// [Rules::enable].
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct FloorPlan {
+    pub width: usize,
+    pub height: usize,
+    pub desk_count: usize,
+    pub desk: DeskShape,
+    pub rules: Rules,
+    pub floor: Floor,
+}
+
+impl FloorPlan {
+    pub fn open(width: usize, height: usize, desk_count: usize, desk: DeskShape) -> Self {
+        FloorPlan { width, height, desk_count, desk, rules: Rules::default(), floor: Floor::open(width, height) }
+    }
+}
+
+impl Subtask for FloorPlan {}
+
+impl FromStr for FloorPlan {
+    type Err = eyre::Report;
+
+    /// Parses just the room line, leaving the floor open
+    fn from_str(line: &str) -> Result<Self, Self::Err> {
+        let mut values = line.split_whitespace();
+        let width = values.next().ok_or(eyre!("Missing width"))?.parse().wrap_err("Invalid width")?;
+        let height = values.next().ok_or(eyre!("Missing height"))?.parse().wrap_err("Invalid height")?;
+        let desk_count = values.next().ok_or(eyre!("Missing desk count"))?;
+
+        let (desk, rules) = Rules::parse_columns(values, DeskShape::new(2, 1))?;
+
+        Ok(FloorPlan {
+            width,
+            height,
+            desk_count: desk_count.parse().wrap_err("Invalid desk count")?,
+            desk,
+            rules,
+            floor: Floor::open(width, height),
+        })
+    }
+}
+
+impl FromLines for FloorPlan {
+    fn from_lines(lines: &mut Lines<'_>) -> Result<Self, eyre::Report> {
+        let line = lines.next().ok_or(eyre!("No line available to parse"))?;
+        let plan = line.parse::<FloorPlan>()?;
+
+        Ok(FloorPlan {
+            floor: Floor::from_lines(lines, plan.width, plan.height).wrap_err("Invalid floor")?,
+            ..plan
+        })
+    }
+}
@@ -0,0 +1,300 @@
+//! Desk placement as boolean satisfiability
+//!
+//! Every possible desk position becomes a variable, positions breaking the spacing rule
+//! together exclude each other and a cardinality constraint asks for the number of desks, or
+//! for chebyshev spacings the number of cells their boxes leave uncovered.
+
+use std::collections::HashSet;
+use std::fmt::{Display, Formatter};
+
+use itertools::Itertools;
+
+use crate::budget::Meter;
+use crate::layout::{Desk, Layout};
+use crate::room::{DeskShape, Metric};
+
+mod cdcl;
+
+pub use cdcl::{Outcome, Solver};
+
+/// Rooms with up to this many cells are small enough to be solved as a formula
+pub const CELL_LIMIT: usize = 1500;
+
+/// A formula in conjunctive normal form over DIMACS literals
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct Cnf {
+    variables: usize,
+    clauses: Vec<Vec<i32>>,
+}
+
+impl Cnf {
+    pub fn new_variable(&mut self) -> i32 {
+        self.variables += 1;
+        self.variables as i32
+    }
+
+    pub fn add(&mut self, clause: impl IntoIterator<Item = i32>) {
+        self.clauses.push(clause.into_iter().collect());
+    }
+
+    /// Requires at most `count` of the literals to be true, using a sequential counter
+    ///
+    /// The counter variable for `i` and `j` is implied by at least `j` of the first `i`
+    /// literals being true.
+    pub fn at_most(&mut self, literals: &[i32], count: usize) {
+        if count >= literals.len() {
+            return;
+        }
+        if count == 0 {
+            for literal in literals {
+                self.add([-literal]);
+            }
+            return;
+        }
+
+        let mut previous: Vec<i32> = Vec::new();
+        for (i, literal) in literals.iter().enumerate() {
+            let counters = (0..count).map(|_| self.new_variable()).collect::<Vec<_>>();
+
+            self.add([-literal, counters[0]]);
+            for (j, counter) in counters.iter().enumerate() {
+                if let Some(carried) = previous.get(j) {
+                    self.add([-carried, *counter]);
+                }
+                if let Some(below) = j.checked_sub(1).and_then(|below| previous.get(below)) {
+                    self.add([-literal, -below, *counter]);
+                }
+            }
+            if i > 0 {
+                self.add([-literal, -previous[count - 1]]);
+            }
+
+            previous = counters;
+        }
+    }
+
+    /// Requires at least `count` of the literals to be true, using a sequential counter
+    ///
+    /// The counter variable for `i` and `j` implies that at least `j` of the first `i`
+    /// literals are true. Only the counters that can still reach `count` are created.
+    pub fn at_least(&mut self, literals: &[i32], count: usize) {
+        let n = literals.len();
+        if count == 0 {
+            return;
+        }
+        if count > n {
+            self.add([]);
+            return;
+        }
+
+        // counters[i][j] for at least j of the first i literals, None where j is out of reach
+        let mut counters = vec![vec![None; count + 1]; n + 1];
+        for (i, row) in counters.iter_mut().enumerate().skip(1) {
+            for counter in &mut row[count.saturating_sub(n - i).max(1)..=i.min(count)] {
+                *counter = Some(self.new_variable());
+            }
+        }
+
+        for i in 1..=n {
+            let literal = literals[i - 1];
+            for j in 1..=count {
+                let Some(counter) = counters[i][j] else { continue };
+
+                // Either the first i - 1 literals already count j, or the ith literal is true
+                // and the first i - 1 count j - 1
+                let carried = counters[i - 1][j];
+                self.add([-counter].into_iter().chain(carried).chain([literal]));
+                if j > 1 {
+                    self.add([-counter].into_iter().chain(carried).chain(counters[i - 1][j - 1]));
+                }
+            }
+        }
+
+        self.add(counters[n][count]);
+    }
+
+    pub fn solver(&self) -> Solver {
+        let mut solver = Solver::new(self.variables);
+        for clause in &self.clauses {
+            solver.add_clause(clause);
+        }
+        solver
+    }
+}
+
+impl Display for Cnf {
+    /// Writes the formula in the DIMACS format read by most SAT solvers
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "p cnf {} {}", self.variables, self.clauses.len())?;
+        for clause in &self.clauses {
+            for literal in clause {
+                write!(f, "{literal} ")?;
+            }
+            writeln!(f, "0")?;
+        }
+        Ok(())
+    }
+}
+
+/// The formula for placing a number of desks into a room and the desk of each variable
+pub struct Encoding {
+    pub cnf: Cnf,
+    /// The desk that variable `i + 1` places
+    pub desks: Vec<Desk>,
+}
+
+impl Encoding {
+    /// Encodes adding `count` desks of the given shape to the desks and rules of `room`
+    pub fn new(room: &Layout, shape: DeskShape, count: usize) -> Self {
+        let desks = room.candidates(shape);
+        let mut cnf = Cnf::default();
+        let variables = desks.iter().map(|_| cnf.new_variable()).collect::<Vec<_>>();
+
+        if room.rules().spacing.metric == Metric::Chebyshev {
+            Self::encode_cover(&mut cnf, room, shape, count, &desks);
+        } else {
+            for (a, b) in room.conflicts(&desks) {
+                cnf.add([-variables[a], -variables[b]]);
+            }
+            cnf.at_least(&variables, count);
+        }
+
+        Encoding { cnf, desks }
+    }
+
+    /// Encodes the desks grown to the right and bottom by the gap as boxes that may not overlap
+    /// in the room grown by the gap
+    ///
+    /// This is the same as keeping a chebyshev spacing. As all boxes have the same area, `count`
+    /// desks leave a fixed number of cells uncovered, which propagates much better on tightly
+    /// packed rooms than counting the desks themselves.
+    fn encode_cover(cnf: &mut Cnf, room: &Layout, shape: DeskShape, count: usize, desks: &[Desk]) {
+        let gap = room.rules().spacing.gap;
+        let cells = (room.width + gap) * (room.height + gap);
+        let area = (shape.length + gap) * (shape.thickness + gap);
+
+        let mut excluded = HashSet::new();
+        let mut uncovered = Vec::with_capacity(cells);
+        for boxes in room.boxes(desks) {
+            let boxes = boxes.into_iter().map(|index| index as i32 + 1).collect::<Vec<_>>();
+            for (a, b) in boxes.iter().tuple_combinations() {
+                if excluded.insert((*a, *b)) {
+                    cnf.add([-a, -b]);
+                }
+            }
+
+            let cell = cnf.new_variable();
+            cnf.add(boxes.into_iter().chain([cell]));
+            uncovered.push(cell);
+        }
+
+        match cells.checked_sub(count * area) {
+            Some(slack) => cnf.at_most(&uncovered, slack),
+            None => cnf.add([]),
+        }
+    }
+
+    /// The room with the desks of a satisfying assignment added
+    pub fn decode(&self, room: &Layout, model: &[bool]) -> Layout {
+        let mut layout = room.clone();
+        for (desk, placed) in self.desks.iter().zip(model) {
+            if *placed {
+                layout.place(*desk);
+            }
+        }
+        layout
+    }
+
+    /// A clause excluding exactly the desks a satisfying assignment places
+    fn exclude(&self, model: &[bool]) -> Vec<i32> {
+        (0..self.desks.len()).map(|index| if model[index] { -(index as i32 + 1) } else { index as i32 + 1 }).collect()
+    }
+}
+
+impl Display for Encoding {
+    /// Writes the formula in the DIMACS format, preceded by comments naming the desk of each
+    /// placement variable
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        for (index, desk) in self.desks.iter().enumerate() {
+            writeln!(f, "c {} {}x{} desk at {},{}", index + 1, desk.width, desk.height, desk.x, desk.y)?;
+        }
+        write!(f, "{}", self.cnf)
+    }
+}
+
+/// Adds `count` desks to `room` by solving the encoded formula, or proves that they do not fit
+///
+/// Returns `None` if there is no such layout, or if the budget runs out before the formula is
+/// decided. Walkway and chair rules are not part of the formula, assignments breaking them are
+/// excluded one by one until one keeps them.
+pub fn place(room: &Layout, shape: DeskShape, count: usize, meter: &Meter) -> Option<Layout> {
+    let encoding = Encoding::new(room, shape, count);
+    let mut solver = encoding.cnf.solver();
+
+    loop {
+        match solver.solve(meter) {
+            Outcome::Satisfiable(model) => {
+                let mut layout = encoding.decode(room, &model);
+                layout.truncate(room.len() + count);
+                if layout.follows_rules() {
+                    tracing::debug!(conflicts = solver.conflicts, "Formula satisfied");
+                    return Some(layout);
+                }
+                solver.add_clause(&encoding.exclude(&model));
+            }
+            Outcome::Unsatisfiable => {
+                tracing::debug!(conflicts = solver.conflicts, "Formula unsatisfiable");
+                return None;
+            }
+            Outcome::Unknown => {
+                tracing::debug!(conflicts = solver.conflicts, "Budget ran out before the formula was decided");
+                return None;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{place, Cnf, Encoding, Outcome};
+    use crate::budget::Meter;
+    use crate::exact;
+    use crate::layout::Layout;
+    use crate::room::{DeskShape, Metric, Rules, Spacing};
+
+    #[test]
+    pub fn test_cardinality() {
+        let mut cnf = Cnf::default();
+        let variables = (0..5).map(|_| cnf.new_variable()).collect::<Vec<_>>();
+        cnf.at_least(&variables, 2);
+        cnf.at_most(&variables, 3);
+
+        let mut solver = cnf.solver();
+        let mut models = 0;
+        while let Outcome::Satisfiable(model) = solver.solve(&Meter::default()) {
+            let count = model[..5].iter().filter(|value| **value).count();
+            assert!((2..=3).contains(&count), "{count} variables are true");
+            models += 1;
+            solver.add_clause(&variables.iter().map(|v| if model[*v as usize - 1] { -v } else { *v }).collect::<Vec<_>>());
+        }
+        assert_eq!(models, 20);
+    }
+
+    #[test]
+    pub fn test_placement() {
+        let manhattan = Rules { spacing: Spacing::new(Metric::Manhattan, 1), ..Rules::default() };
+        for (width, height, rules) in [(7, 7, Rules::default()), (6, 5, manhattan), (5, 4, Rules::default())] {
+            let room = Layout::new(width, height).under(rules);
+            let optimum = exact::maximise(&room, DeskShape::new(2, 1), &Meter::default()).len();
+
+            let layout = place(&room, DeskShape::new(2, 1), optimum, &Meter::default()).unwrap();
+            assert_eq!(layout.len(), optimum);
+            layout.check_spacing().unwrap();
+            assert!(place(&room, DeskShape::new(2, 1), optimum + 1, &Meter::default()).is_none());
+        }
+
+        let dimacs = Encoding::new(&Layout::new(4, 3), DeskShape::new(2, 1), 2).to_string();
+        assert!(dimacs.lines().any(|line| line.starts_with("p cnf ")));
+        assert!(dimacs.lines().filter(|line| !line.starts_with(['c', 'p'])).all(|line| line.ends_with(" 0") || line == "0"));
+    }
+}
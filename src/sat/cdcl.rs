@@ -0,0 +1,437 @@
+//! A conflict driven clause learning SAT solver
+//!
+//! Uses two watched literals for unit propagation, learns first unique implication point
+//! clauses, picks decisions by variable activity with phase saving and restarts after a Luby
+//! sequence of conflicts.
+
+use crate::budget::Meter;
+
+/// A variable or its negation, encoded as `2 * variable + negated`
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+struct Lit(u32);
+
+impl Lit {
+    fn new(variable: usize, negated: bool) -> Self {
+        Lit((variable as u32) << 1 | u32::from(negated))
+    }
+
+    /// Converts a non-zero DIMACS literal, where variables start at 1 and `-v` negates `v`
+    fn from_dimacs(literal: i32) -> Self {
+        Lit::new(literal.unsigned_abs() as usize - 1, literal < 0)
+    }
+
+    fn variable(self) -> usize {
+        (self.0 >> 1) as usize
+    }
+
+    fn is_negated(self) -> bool {
+        self.0 & 1 == 1
+    }
+
+    fn index(self) -> usize {
+        self.0 as usize
+    }
+}
+
+impl std::ops::Not for Lit {
+    type Output = Lit;
+
+    fn not(self) -> Lit {
+        Lit(self.0 ^ 1)
+    }
+}
+
+/// The result of solving a formula
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Outcome {
+    /// An assignment satisfying every clause, indexed by variable starting at 0
+    Satisfiable(Vec<bool>),
+    Unsatisfiable,
+    /// The budget ran out before the formula was decided
+    Unknown,
+}
+
+/// A max-heap of variables ordered by their activity
+#[derive(Default)]
+struct Order {
+    heap: Vec<usize>,
+    position: Vec<Option<usize>>,
+}
+
+impl Order {
+    fn contains(&self, variable: usize) -> bool {
+        self.position[variable].is_some()
+    }
+
+    fn insert(&mut self, variable: usize, activity: &[f64]) {
+        if !self.contains(variable) {
+            self.position[variable] = Some(self.heap.len());
+            self.heap.push(variable);
+            self.sift_up(self.heap.len() - 1, activity);
+        }
+    }
+
+    fn pop(&mut self, activity: &[f64]) -> Option<usize> {
+        let top = *self.heap.first()?;
+        let last = self.heap.pop()?;
+        self.position[top] = None;
+        if !self.heap.is_empty() {
+            self.heap[0] = last;
+            self.position[last] = Some(0);
+            self.sift_down(0, activity);
+        }
+        Some(top)
+    }
+
+    fn bumped(&mut self, variable: usize, activity: &[f64]) {
+        if let Some(position) = self.position[variable] {
+            self.sift_up(position, activity);
+        }
+    }
+
+    fn swap(&mut self, a: usize, b: usize) {
+        self.heap.swap(a, b);
+        self.position[self.heap[a]] = Some(a);
+        self.position[self.heap[b]] = Some(b);
+    }
+
+    fn sift_up(&mut self, mut position: usize, activity: &[f64]) {
+        while position > 0 {
+            let parent = (position - 1) / 2;
+            if activity[self.heap[parent]] >= activity[self.heap[position]] {
+                break;
+            }
+            self.swap(parent, position);
+            position = parent;
+        }
+    }
+
+    fn sift_down(&mut self, mut position: usize, activity: &[f64]) {
+        loop {
+            let (left, right) = (2 * position + 1, 2 * position + 2);
+            let mut largest = position;
+            if left < self.heap.len() && activity[self.heap[left]] > activity[self.heap[largest]] {
+                largest = left;
+            }
+            if right < self.heap.len() && activity[self.heap[right]] > activity[self.heap[largest]] {
+                largest = right;
+            }
+            if largest == position {
+                break;
+            }
+            self.swap(position, largest);
+            position = largest;
+        }
+    }
+}
+
+/// The `index`th element of the Luby sequence 1, 1, 2, 1, 1, 2, 4, 1, ...
+fn luby(mut index: u64) -> u64 {
+    let (mut size, mut power) = (1, 0);
+    while size < index + 1 {
+        power += 1;
+        size = 2 * size + 1;
+    }
+    while size - 1 != index {
+        size = (size - 1) / 2;
+        power -= 1;
+        index %= size;
+    }
+    1 << power
+}
+
+const RESTART_INTERVAL: u64 = 100;
+const ACTIVITY_DECAY: f64 = 0.95;
+
+pub struct Solver {
+    clauses: Vec<Vec<Lit>>,
+    /// The clauses watching each literal, i.e. having it at position 0 or 1
+    watches: Vec<Vec<usize>>,
+    assignment: Vec<Option<bool>>,
+    level: Vec<usize>,
+    reason: Vec<Option<usize>>,
+    trail: Vec<Lit>,
+    trail_limits: Vec<usize>,
+    propagated: usize,
+    activity: Vec<f64>,
+    increment: f64,
+    order: Order,
+    phase: Vec<bool>,
+    seen: Vec<bool>,
+    consistent: bool,
+    pub conflicts: u64,
+}
+
+impl Solver {
+    pub fn new(variables: usize) -> Self {
+        let mut solver = Solver {
+            clauses: Vec::new(),
+            watches: vec![Vec::new(); 2 * variables],
+            assignment: vec![None; variables],
+            level: vec![0; variables],
+            reason: vec![None; variables],
+            trail: Vec::new(),
+            trail_limits: Vec::new(),
+            propagated: 0,
+            activity: vec![0.0; variables],
+            increment: 1.0,
+            order: Order { heap: Vec::new(), position: vec![None; variables] },
+            phase: vec![false; variables],
+            seen: vec![false; variables],
+            consistent: true,
+            conflicts: 0,
+        };
+        for variable in 0..variables {
+            solver.order.insert(variable, &solver.activity);
+        }
+        solver
+    }
+
+    fn value(&self, lit: Lit) -> Option<bool> {
+        self.assignment[lit.variable()].map(|value| value != lit.is_negated())
+    }
+
+    fn decision_level(&self) -> usize {
+        self.trail_limits.len()
+    }
+
+    /// Adds a clause of DIMACS literals, before solving or between two calls of [Solver::solve]
+    ///
+    /// Clauses are simplified against the assignment, which is only sound at decision level 0.
+    /// [Solver::solve] backtracks to it before returning, so that clauses can be added to exclude
+    /// a model and solve again.
+    pub fn add_clause(&mut self, clause: &[i32]) {
+        debug_assert_eq!(self.decision_level(), 0, "Clauses can only be added at decision level 0");
+        let mut lits = clause.iter().map(|literal| Lit::from_dimacs(*literal)).collect::<Vec<_>>();
+        lits.sort_by_key(|lit| lit.0);
+        lits.dedup();
+        if lits.windows(2).any(|pair| pair[0] == !pair[1]) || lits.iter().any(|lit| self.value(*lit) == Some(true)) {
+            return;
+        }
+        lits.retain(|lit| self.value(*lit).is_none());
+
+        match lits[..] {
+            [] => self.consistent = false,
+            [lit] => {
+                self.assign(lit, None);
+                self.consistent &= self.propagate().is_none();
+            }
+            _ => {
+                self.attach(lits);
+            }
+        }
+    }
+
+    fn attach(&mut self, lits: Vec<Lit>) -> usize {
+        let index = self.clauses.len();
+        self.watches[lits[0].index()].push(index);
+        self.watches[lits[1].index()].push(index);
+        self.clauses.push(lits);
+        index
+    }
+
+    fn assign(&mut self, lit: Lit, reason: Option<usize>) {
+        let variable = lit.variable();
+        self.assignment[variable] = Some(!lit.is_negated());
+        self.level[variable] = self.decision_level();
+        self.reason[variable] = reason;
+        self.trail.push(lit);
+    }
+
+    /// Assigns all unit literals, returning the clause that became false if there is one
+    fn propagate(&mut self) -> Option<usize> {
+        while self.propagated < self.trail.len() {
+            let falsified = !self.trail[self.propagated];
+            self.propagated += 1;
+
+            let watching = std::mem::take(&mut self.watches[falsified.index()]);
+            let mut kept = Vec::with_capacity(watching.len());
+            let mut conflict = None;
+
+            for (position, &index) in watching.iter().enumerate() {
+                if conflict.is_some() {
+                    kept.extend_from_slice(&watching[position..]);
+                    break;
+                }
+
+                let clause = &mut self.clauses[index];
+                if clause[0] == falsified {
+                    clause.swap(0, 1);
+                }
+
+                let first = clause[0];
+                if self.assignment[first.variable()].is_some_and(|value| value != first.is_negated()) {
+                    kept.push(index);
+                    continue;
+                }
+
+                let replacement = (2..clause.len()).find(|k| {
+                    let lit = clause[*k];
+                    self.assignment[lit.variable()].is_none_or(|value| value != lit.is_negated())
+                });
+                if let Some(k) = replacement {
+                    clause.swap(1, k);
+                    let watched = clause[1];
+                    self.watches[watched.index()].push(index);
+                    continue;
+                }
+
+                kept.push(index);
+                match self.value(first) {
+                    Some(false) => conflict = Some(index),
+                    _ => self.assign(first, Some(index)),
+                }
+            }
+
+            self.watches[falsified.index()] = kept;
+            if conflict.is_some() {
+                return conflict;
+            }
+        }
+        None
+    }
+
+    fn bump(&mut self, variable: usize) {
+        self.activity[variable] += self.increment;
+        if self.activity[variable] > 1e100 {
+            self.activity.iter_mut().for_each(|activity| *activity *= 1e-100);
+            self.increment *= 1e-100;
+        }
+        self.order.bumped(variable, &self.activity);
+    }
+
+    /// Derives the first unique implication point clause of a conflict and the level to jump
+    /// back to
+    fn analyze(&mut self, mut conflict: usize) -> (Vec<Lit>, usize) {
+        let mut learnt = vec![Lit(0)];
+        let mut pending = 0;
+        let mut implied: Option<Lit> = None;
+        let mut index = self.trail.len();
+
+        loop {
+            let skip = usize::from(implied.is_some());
+            for k in skip..self.clauses[conflict].len() {
+                let lit = self.clauses[conflict][k];
+                let variable = lit.variable();
+                if !self.seen[variable] && self.level[variable] > 0 {
+                    self.bump(variable);
+                    self.seen[variable] = true;
+                    if self.level[variable] >= self.decision_level() {
+                        pending += 1;
+                    } else {
+                        learnt.push(lit);
+                    }
+                }
+            }
+
+            loop {
+                index -= 1;
+                if self.seen[self.trail[index].variable()] {
+                    break;
+                }
+            }
+            let lit = self.trail[index];
+            self.seen[lit.variable()] = false;
+            implied = Some(lit);
+            pending -= 1;
+            if pending == 0 {
+                break;
+            }
+            conflict = self.reason[lit.variable()].expect("Implied literals have a reason");
+        }
+
+        learnt[0] = !implied.expect("A conflict involves the current decision level");
+        for lit in &learnt[1..] {
+            self.seen[lit.variable()] = false;
+        }
+
+        let mut backjump = 0;
+        if learnt.len() > 1 {
+            let highest = (1..learnt.len()).max_by_key(|k| self.level[learnt[*k].variable()]).unwrap_or(1);
+            learnt.swap(1, highest);
+            backjump = self.level[learnt[1].variable()];
+        }
+
+        (learnt, backjump)
+    }
+
+    fn backtrack(&mut self, level: usize) {
+        if self.decision_level() <= level {
+            return;
+        }
+
+        let limit = self.trail_limits[level];
+        for lit in self.trail.drain(limit..) {
+            let variable = lit.variable();
+            self.assignment[variable] = None;
+            self.phase[variable] = !lit.is_negated();
+            self.order.insert(variable, &self.activity);
+        }
+        self.trail_limits.truncate(level);
+        self.propagated = self.trail.len();
+    }
+
+    fn decide(&mut self) -> Option<Lit> {
+        while let Some(variable) = self.order.pop(&self.activity) {
+            if self.assignment[variable].is_none() {
+                return Some(Lit::new(variable, !self.phase[variable]));
+            }
+        }
+        None
+    }
+
+    /// Decides the formula, spending a search node of the budget on every conflict
+    pub fn solve(&mut self, meter: &Meter) -> Outcome {
+        if !self.consistent || self.propagate().is_some() {
+            self.consistent = false;
+            return Outcome::Unsatisfiable;
+        }
+
+        let mut restarts = 0;
+        let mut restart_at = self.conflicts + RESTART_INTERVAL * luby(restarts);
+
+        loop {
+            if let Some(conflict) = self.propagate() {
+                self.conflicts += 1;
+                if self.decision_level() == 0 {
+                    self.consistent = false;
+                    return Outcome::Unsatisfiable;
+                }
+                if !meter.spend() {
+                    self.backtrack(0);
+                    return Outcome::Unknown;
+                }
+
+                let (learnt, backjump) = self.analyze(conflict);
+                self.backtrack(backjump);
+                match learnt.len() {
+                    1 => self.assign(learnt[0], None),
+                    _ => {
+                        let implied = learnt[0];
+                        let index = self.attach(learnt);
+                        self.assign(implied, Some(index));
+                    }
+                }
+                self.increment /= ACTIVITY_DECAY;
+
+                if self.conflicts >= restart_at {
+                    restarts += 1;
+                    restart_at = self.conflicts + RESTART_INTERVAL * luby(restarts);
+                    self.backtrack(0);
+                }
+            } else {
+                match self.decide() {
+                    Some(lit) => {
+                        self.trail_limits.push(self.trail.len());
+                        self.assign(lit, None);
+                    }
+                    None => {
+                        let model = self.assignment.iter().map(|value| value.unwrap_or(false)).collect();
+                        self.backtrack(0);
+                        return Outcome::Satisfiable(model);
+                    }
+                }
+            }
+        }
+    }
+}
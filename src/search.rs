@@ -0,0 +1,207 @@
+//! Local search that improves a layout by re-placing the desks around random spots
+//!
+//! Every step removes the desks near a random cell and places desks of the room's shape
+//! around it again in random order. Steps that add desks are always kept, steps that lose
+//! desks only with a probability that shrinks as the search cools down.
+
+use std::time::{Duration, Instant};
+
+use serde::Serialize;
+
+use crate::budget::Meter;
+use crate::layout::{Desk, Layout};
+use crate::room::DeskShape;
+
+/// A small deterministic random number generator (SplitMix64)
+struct Random(u64);
+
+impl Random {
+    fn next(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    fn below(&mut self, bound: usize) -> usize {
+        (self.next() % bound as u64) as usize
+    }
+
+    /// A uniformly distributed number in `0..1`
+    fn unit(&mut self) -> f64 {
+        (self.next() >> 11) as f64 / (1u64 << 53) as f64
+    }
+
+    fn shuffle<T>(&mut self, items: &mut [T]) {
+        for index in (1..items.len()).rev() {
+            items.swap(index, self.below(index + 1));
+        }
+    }
+}
+
+/// How much a local search improved the layout it started from
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Serialize)]
+pub struct Improvement {
+    /// The strategy that found the starting layout
+    pub from: &'static str,
+    pub start: usize,
+    pub end: usize,
+    pub iterations: usize,
+}
+
+/// Settings of a local search
+///
+/// The search is deterministic for a seed as long as the iteration budget runs out before the
+/// time limit and the budget of the subtask.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct LocalSearch {
+    pub seed: u64,
+    pub iterations: usize,
+    pub time_limit: Duration,
+    /// Stop as soon as this many desks are placed
+    pub target: Option<usize>,
+}
+
+impl Default for LocalSearch {
+    fn default() -> Self {
+        LocalSearch { seed: 2024, iterations: 200_000, time_limit: Duration::from_secs(2), target: None }
+    }
+}
+
+/// The temperature at the start of the search, in desks
+const INITIAL_TEMPERATURE: f64 = 0.6;
+
+struct State {
+    layout: Layout,
+    /// The index of the desk on every cell, offset by one, like [Layout::grid]
+    grid: Vec<usize>,
+}
+
+impl State {
+    fn add(&mut self, desk: Desk) {
+        self.layout.place(desk);
+        self.mark(&desk, self.layout.len());
+    }
+
+    fn remove(&mut self, index: usize) -> Desk {
+        let desk = self.layout.desks.swap_remove(index);
+        self.mark(&desk, 0);
+        if let Some(moved) = self.layout.desks.get(index).copied() {
+            self.mark(&moved, index + 1);
+        }
+        desk
+    }
+
+    fn mark(&mut self, desk: &Desk, id: usize) {
+        for (x, y) in desk.cells() {
+            self.grid[y * self.layout.width + x] = id;
+        }
+    }
+}
+
+impl LocalSearch {
+    /// Tries to add desks of the given shape to a layout, returning the best layout found and
+    /// the number of iterations run, each of which spends a search node of the budget
+    pub fn improve(&self, start: &Layout, shape: DeskShape, meter: &Meter) -> (Layout, usize) {
+        let mut random = Random(self.seed);
+        let mut state = State { grid: start.grid(), layout: start.clone() };
+        let mut best = start.clone();
+        let constrained = start.is_constrained();
+
+        let (width, height) = (start.width, start.height);
+        if width == 0 || height == 0 {
+            return (best, 0);
+        }
+        let radius = shape.length + start.rules().spacing.gap;
+        let deadline = Instant::now() + self.time_limit;
+
+        let mut iterations = 0;
+        while iterations < self.iterations && self.target.is_none_or(|target| best.len() < target) {
+            if (iterations % 256 == 0 && Instant::now() >= deadline) || !meter.spend() {
+                break;
+            }
+            iterations += 1;
+
+            let (cx, cy) = (random.below(width), random.below(height));
+            let (left, top) = (cx.saturating_sub(radius), cy.saturating_sub(radius));
+            let (right, bottom) = ((cx + radius).min(width - 1), (cy + radius).min(height - 1));
+
+            let mut near = (top..=bottom)
+                .flat_map(|y| (left..=right).map(move |x| y * width + x))
+                .map(|cell| state.grid[cell])
+                .filter(|id| *id != 0)
+                .collect::<Vec<_>>();
+            near.sort_unstable_by(|a, b| b.cmp(a));
+            near.dedup();
+            let removed = near.into_iter().map(|id| state.remove(id - 1)).collect::<Vec<_>>();
+
+            let mut candidates = (top.saturating_sub(shape.length)..=bottom)
+                .flat_map(|y| (left.saturating_sub(shape.length)..=right).map(move |x| (x, y)))
+                .flat_map(|(x, y)| shape.orientations().into_iter().map(move |size| Desk::new(x, y, size)))
+                .collect::<Vec<_>>();
+            random.shuffle(&mut candidates);
+
+            let before = state.layout.len();
+            for desk in candidates {
+                if state.layout.is_free(&state.grid, &desk) {
+                    state.add(desk);
+                }
+            }
+            let added = state.layout.len() - before;
+
+            let delta = added as f64 - removed.len() as f64;
+            let temperature = INITIAL_TEMPERATURE * (1.0 - iterations as f64 / self.iterations as f64);
+            let accepted = (delta >= 0.0 || random.unit() < (delta / temperature).exp())
+                && (!constrained || state.layout.follows_rules());
+
+            if accepted {
+                if state.layout.len() > best.len() {
+                    best = state.layout.clone();
+                }
+            } else {
+                for _ in 0..added {
+                    state.remove(state.layout.len() - 1);
+                }
+                for desk in removed {
+                    state.add(desk);
+                }
+            }
+        }
+
+        (best, iterations)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::LocalSearch;
+    use crate::budget::Meter;
+    use crate::exact;
+    use crate::layout::{Desk, Layout};
+    use crate::room::{DeskShape, Rules};
+
+    #[test]
+    pub fn test_improve() {
+        let shape = DeskShape::new(2, 1);
+        let room = Layout::new(7, 7).under(Rules { chairs: true, ..Rules::default() });
+        let optimum = exact::maximise(&room, shape, &Meter::default()).len();
+
+        // A desk in the middle of the room stands in the way of the optimal patterns
+        let mut start = room.clone();
+        start.place(Desk::new(3, 3, (2, 1)));
+        start.fill_greedy(shape);
+
+        let search = LocalSearch { target: Some(optimum), ..LocalSearch::default() };
+        let (layout, iterations) = search.improve(&start, shape, &Meter::default());
+        assert!(start.len() < optimum);
+        assert_eq!(layout.len(), optimum);
+        assert!(iterations < search.iterations);
+        layout.check_spacing().unwrap();
+        layout.check_chairs().unwrap();
+
+        let again = search.improve(&start, shape, &Meter::default());
+        assert_eq!(again.0.desks, layout.desks);
+        assert_eq!(again.1, iterations);
+    }
+}
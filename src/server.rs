@@ -0,0 +1,175 @@
+//! A local HTTP service that solves and verifies subtasks, e.g. for the visualizer
+//!
+//! `POST /solve` takes `{"level": 5, "input": "..."}` with the text of an input file, or
+//! `{"level": 5, "room": "9 7 10"}` with a single room line, and answers with the output and
+//! the report of the task. `POST /verify` takes the same and an `"output"` to verify. Failed
+//! tasks are answered with status 422 and their errors, solvers that panic with status 500.
+//! Responses allow any origin, so that pages opened from disk can call the service.
+
+use eyre::{eyre, WrapErr};
+use serde::{Deserialize, Serialize};
+use tiny_http::{Header, Method, Response, Server};
+
+use crate::levels::{self, Settings};
+use crate::report::{self, TaskReport};
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Request {
+    level: usize,
+    input: Option<String>,
+    room: Option<String>,
+    output: Option<String>,
+}
+
+#[derive(Serialize)]
+struct Answer {
+    ok: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    output: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    report: Option<TaskReport>,
+    errors: Vec<String>,
+}
+
+impl Answer {
+    fn error(status: u16, error: impl Into<String>) -> (u16, String) {
+        (status, Answer { ok: false, output: None, report: None, errors: vec![error.into()] }.to_json())
+    }
+
+    fn to_json(&self) -> String {
+        serde_json::to_string(self).expect("Answers serialize")
+    }
+}
+
+/// Answers requests on `address` until the process is stopped
+pub fn serve(address: &str, settings: &Settings) -> eyre::Result<()> {
+    let server = Server::http(address).map_err(|error| eyre!(error)).wrap_err(format!("Cannot listen on {address}"))?;
+    tracing::info!("Listening on http://{address}");
+
+    for mut request in server.incoming_requests() {
+        let _span = tracing::info_span!("request", method = %request.method(), url = request.url()).entered();
+        let (status, body) = match request.method() {
+            Method::Options => (204, String::new()),
+            Method::Post => {
+                let mut body = String::new();
+                match request.as_reader().read_to_string(&mut body) {
+                    Ok(_) => handle(request.url(), &body, settings),
+                    Err(error) => Answer::error(400, format!("Cannot read the request body: {error}")),
+                }
+            }
+            _ => Answer::error(405, "Only POST requests are answered"),
+        };
+        tracing::info!(status, "Answered");
+
+        let response = Response::from_string(body)
+            .with_status_code(status)
+            .with_header(header("Content-Type", "application/json"))
+            .with_header(header("Access-Control-Allow-Origin", "*"))
+            .with_header(header("Access-Control-Allow-Methods", "POST, OPTIONS"))
+            .with_header(header("Access-Control-Allow-Headers", "Content-Type"));
+        if let Err(error) = request.respond(response) {
+            tracing::warn!("Failed to answer: {error}");
+        }
+    }
+
+    Ok(())
+}
+
+fn header(name: &str, value: &str) -> Header {
+    Header::from_bytes(name, value).expect("Header names and values are ASCII")
+}
+
+/// Answers the JSON body of a request to `path` with a status code and a JSON body
+///
+/// A panicking solver is answered with status 500, so that the service keeps running.
+pub fn handle(path: &str, body: &str, settings: &Settings) -> (u16, String) {
+    report::catch_panic(|| answer(path, body, settings))
+        .unwrap_or_else(|error| Answer::error(500, error.to_string()))
+}
+
+fn answer(path: &str, body: &str, settings: &Settings) -> (u16, String) {
+    if !matches!(path, "/solve" | "/verify") {
+        return Answer::error(404, format!("Unknown path '{path}', use /solve or /verify"));
+    }
+
+    let request = match serde_json::from_str::<Request>(body) {
+        Ok(request) => request,
+        Err(error) => return Answer::error(400, format!("Invalid request: {error}")),
+    };
+    let level = match levels::find(request.level) {
+        Ok(level) => level,
+        Err(error) => return Answer::error(400, error.to_string()),
+    };
+    let input = match (request.input, request.room) {
+        (Some(input), None) => input,
+        (None, Some(room)) => format!("1\n{}\n", room.trim()),
+        _ => return Answer::error(400, "Give either an input or a room"),
+    };
+
+    let mut report = TaskReport::new(1);
+    let (output, result) = if path == "/solve" {
+        match (level.solve)(&input, settings, &mut report) {
+            Ok(output) => (Some(output), Ok(())),
+            Err(errors) => (None, Err(errors)),
+        }
+    } else {
+        let Some(output) = request.output else {
+            return Answer::error(400, "Give the output to verify");
+        };
+        (None, (level.verify)(&input, &output, &mut report))
+    };
+
+    let errors = result.err().unwrap_or_default().iter().map(|error| format!("{error:#}")).collect::<Vec<_>>();
+    let status = if errors.is_empty() { 200 } else { 422 };
+    (status, Answer { ok: errors.is_empty(), output, report: Some(report), errors }.to_json())
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::{json, Value};
+
+    use super::handle;
+    use crate::levels::Settings;
+
+    fn request(path: &str, body: Value) -> (u16, Value) {
+        let (status, answer) = handle(path, &body.to_string(), &Settings::default());
+        (status, serde_json::from_str(&answer).unwrap())
+    }
+
+    #[test]
+    pub fn test_handle() {
+        let (status, solved) = request("/solve", json!({ "level": 5, "room": "9 7 10" }));
+        assert_eq!(status, 200, "{solved}");
+        assert_eq!(solved["report"]["status"], "passed");
+
+        let output = solved["output"].as_str().unwrap();
+        let (status, verified) = request("/verify", json!({ "level": 5, "input": "1\n9 7 10\n", "output": output }));
+        assert_eq!((status, &verified["ok"]), (200, &json!(true)));
+
+        let (status, rejected) = request("/verify", json!({ "level": 5, "room": "4 1 2", "output": "XX..\n" }));
+        assert_eq!((status, &rejected["ok"]), (422, &json!(false)));
+        assert_eq!(rejected["report"]["status"], "verify_failed");
+
+        assert_eq!(request("/solve", json!({ "level": 9, "room": "1" })).0, 400);
+        assert_eq!(request("/solve", json!({ "level": 5 })).0, 400);
+        assert_eq!(request("/verify", json!({ "level": 5, "room": "4 1 1" })).0, 400);
+        assert_eq!(request("/place", json!({ "level": 5, "room": "4 1 1" })).0, 404);
+        assert_eq!(request("/solve", json!({ "level": 5, "room": "5 1 2 1x2" })).0, 200);
+        assert_eq!(request("/solve", json!({ "level": 5, "room": "0 0 0" })).0, 200);
+    }
+
+    #[test]
+    pub fn test_visualizer_round_trip() {
+        // The bodies the visualizer sends for level 1, 2, 5 and 6 inputs
+        for (level, room) in [(1, "6 5"), (2, "6 5 10"), (5, "6 6 8"), (5, "9 7 6 3x1")] {
+            let (status, solved) = request("/solve", json!({ "level": level, "room": room }));
+            assert_eq!(status, 200, "{solved}");
+
+            // The visualizer shows the output with its line breaks normalised and sends it back
+            let output = solved["output"].as_str().unwrap().replace("\r\n", "\n").trim().to_string();
+            let (status, verified) = request("/verify", json!({ "level": level, "room": room, "output": output }));
+            assert_eq!((status, &verified["ok"]), (200, &json!(true)), "{verified}");
+        }
+    }
+}
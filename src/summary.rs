@@ -0,0 +1,173 @@
+//! The human readable summary of a run
+
+use std::collections::BTreeMap;
+use std::fmt::{Display, Formatter};
+
+use itertools::Itertools;
+
+use crate::report::{RunReport, TaskReport};
+
+/// Human readable overview of a [RunReport], grouping failures by level and task
+///
+/// Failing tasks are collapsed into counts per failure kind unless `expand` is set,
+/// in which case every failing subtask is listed together with its error messages.
+pub struct Summary<'a> {
+    pub report: &'a RunReport,
+    pub expand: bool,
+}
+
+fn status_counts(task: &TaskReport) -> String {
+    let mut counts = BTreeMap::<String, usize>::new();
+    for subtask in task.subtasks.iter().filter(|subtask| !subtask.status.is_passed()) {
+        *counts.entry(subtask.status.to_string()).or_default() += 1;
+    }
+
+    counts.into_iter()
+        .map(|(status, count)| format!("{count} {status}"))
+        .join(", ")
+}
+
+fn write_messages(f: &mut Formatter<'_>, indent: usize, messages: &[String]) -> std::fmt::Result {
+    for message in messages {
+        writeln!(f, "{:indent$}{message}", "")?;
+    }
+    Ok(())
+}
+
+impl Display for Summary<'_> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let mut failures = 0;
+
+        for level in &self.report.levels {
+            let failed_tasks = level.tasks.iter()
+                .filter(|task| !task.status.is_passed())
+                .collect::<Vec<_>>();
+
+            if level.status.is_passed() {
+                write!(f, "{}: passed {} tasks in {:.0}ms", level.name, level.tasks.len(), level.duration_ms)?;
+                let unproven = level.tasks.iter()
+                    .flat_map(|task| &task.subtasks)
+                    .filter(|subtask| subtask.not_proven_optimal)
+                    .count();
+                if unproven > 0 {
+                    write!(f, ", {unproven} subtasks not proven optimal")?;
+                }
+                writeln!(f)?;
+                continue;
+            }
+
+            if failed_tasks.is_empty() {
+                failures += 1;
+                writeln!(f, "{}: {}", level.name, level.status)?;
+                write_messages(f, 4, &level.messages)?;
+                continue;
+            }
+
+            writeln!(f, "{}: {} of {} tasks failed", level.name, failed_tasks.len(), level.tasks.len())?;
+            for task in failed_tasks {
+                let failed_subtasks = task.subtasks.iter()
+                    .filter(|subtask| !subtask.status.is_passed())
+                    .collect::<Vec<_>>();
+
+                if failed_subtasks.is_empty() {
+                    failures += 1;
+                    writeln!(f, "  task {}: {}", task.task, task.status)?;
+                    if self.expand {
+                        write_messages(f, 4, &task.messages)?;
+                    }
+                    continue;
+                }
+
+                failures += failed_subtasks.len();
+                writeln!(f, "  task {}: {} of {} subtasks", task.task, status_counts(task), task.subtasks.len())?;
+                if self.expand {
+                    write_messages(f, 4, &task.messages)?;
+                    for subtask in failed_subtasks {
+                        writeln!(f, "    subtask {} {}: {}", subtask.subtask, subtask.input, subtask.status)?;
+                        write_messages(f, 6, &subtask.messages)?;
+                    }
+                }
+            }
+        }
+
+        match (failures, self.expand) {
+            (0, _) => Ok(()),
+            (failures, false) => writeln!(f, "{failures} failures, rerun with --expand to list them"),
+            (failures, true) => writeln!(f, "{failures} failures"),
+        }
+    }
+}
+
+/// One line per task with its status, the passed subtasks and the time taken
+pub struct Table<'a>(pub &'a RunReport);
+
+impl Display for Table<'_> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "{:<8} {:>4}  {:<14} {:>9} {:>9}", "level", "task", "status", "subtasks", "time")?;
+
+        for level in &self.0.levels {
+            if level.tasks.is_empty() {
+                writeln!(f, "{:<8} {:>4}  {:<14}", level.name, "-", level.status.to_string())?;
+            }
+
+            for task in &level.tasks {
+                let passed = task.subtasks.iter().filter(|subtask| subtask.status.is_passed()).count();
+                writeln!(
+                    f,
+                    "{:<8} {:>4}  {:<14} {:>9} {:>7.0}ms",
+                    level.name,
+                    task.task,
+                    task.status.to_string(),
+                    format!("{passed}/{}", task.subtasks.len()),
+                    task.duration_ms,
+                )?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Summary;
+    use crate::levels::{self, Settings};
+    use crate::report::{LevelReport, RunReport, Status, TaskReport};
+
+    #[test]
+    pub fn test_grouping() {
+        let level = levels::find(5).unwrap();
+        let settings = Settings::default();
+        let mut solved = LevelReport::new(level.number, level.name);
+        for (n, input) in ["1\n9 7 10\n", "3\n4 1 3\n9 7 10\n4 1 4\n", "1\nnine 7 10\n"].into_iter().enumerate() {
+            let mut task = TaskReport::new(n + 1);
+            let _ = (level.solve)(input, &settings, &mut task);
+            solved.push(task);
+        }
+        let mut unwritable = LevelReport::new(4, "level4");
+        let _ = unwritable.fail(Status::WriteFailed, eyre::eyre!("Cannot create 'out/level4' directory"));
+        let run = RunReport { levels: vec![solved, unwritable], ..RunReport::new() };
+
+        let collapsed = Summary { report: &run, expand: false }.to_string();
+        assert_eq!(collapsed, "\
+level5: 2 of 3 tasks failed
+  task 2: 2 solve failed of 3 subtasks
+  task 3: parse failed
+level4: write failed
+    Cannot create 'out/level4' directory
+4 failures, rerun with --expand to list them
+");
+
+        let expanded = Summary { report: &run, expand: true }.to_string();
+        let subtasks = expanded.lines().filter(|line| line.starts_with("    subtask ")).collect::<Vec<_>>();
+        assert_eq!(subtasks.len(), 2, "{expanded}");
+        assert!(subtasks[0].starts_with("    subtask 1 Room { width: 4") && subtasks[1].starts_with("    subtask 3 Room { width: 4"));
+        assert!(expanded.contains("  task 3: parse failed\n    Failed to parse input\n    Invalid width\n"), "{expanded}");
+        assert!(expanded.ends_with("\n4 failures\n"), "{expanded}");
+
+        let mut passed = RunReport { levels: vec![run.levels[0].clone()], ..RunReport::new() };
+        passed.levels[0].tasks.truncate(1);
+        passed.levels[0].status = Status::Passed;
+        assert!(Summary { report: &passed, expand: false }.to_string().starts_with("level5: passed 1 tasks in "));
+    }
+}
@@ -0,0 +1,167 @@
+//! Layouts for rooms of any size, composed of the optimal layouts of small rooms
+//!
+//! A room is cut into blocks that are separated by strips as wide as the spacing gap, so desks
+//! of different blocks always keep the spacing. Along each axis, blocks of one size repeat and
+//! are followed by at most one block of another size. Every block gets the layout the
+//! [catalog] found for it, and the cut with the most desks is found by trying every size and
+//! rest in the catalog, which takes the same time for any room.
+
+use std::collections::HashMap;
+use std::sync::{Arc, LazyLock, Mutex};
+
+use eyre::{eyre, WrapErr};
+use itertools::Itertools;
+
+use crate::budget::Budget;
+use crate::catalog::{self, Catalog};
+use crate::layout::{Desk, Layout};
+use crate::room::{DeskShape, Rules};
+
+/// The largest width and height of the blocks
+pub const BLOCK_LIMIT: usize = 12;
+
+/// What the sweep may spend on every block, blocks it does not prove still tile correctly
+const BLOCK_BUDGET: Budget = Budget { time: None, nodes: Some(2_000) };
+
+/// The tilings swept so far, per desk shape and rules
+type Tilings = HashMap<(DeskShape, Rules), Arc<Tiling>>;
+
+static TILINGS: LazyLock<Mutex<Tilings>> = LazyLock::new(Mutex::default);
+
+/// How an axis is cut: `count` blocks of `size`, then a block of `rest` unless it is 0
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct Cut {
+    pub size: usize,
+    pub count: usize,
+    pub rest: usize,
+}
+
+impl Cut {
+    /// The offsets and lengths of the blocks along the axis
+    fn blocks(&self, gap: usize) -> impl Iterator<Item = (usize, usize)> {
+        let Cut { size, count, rest } = *self;
+        (0..count)
+            .map(move |index| (index * (size + gap), size))
+            .chain((rest > 0).then_some((count * (size + gap), rest)))
+    }
+}
+
+pub struct Tiling {
+    rules: Rules,
+    /// The layout of every block, indexed by `(height - 1) * BLOCK_LIMIT + width - 1`
+    blocks: Vec<Layout>,
+}
+
+impl Tiling {
+    /// Takes the blocks from a catalog that covers every room up to [BLOCK_LIMIT]
+    pub fn new(catalog: &Catalog, rules: Rules) -> eyre::Result<Self> {
+        let mut blocks = Vec::with_capacity(BLOCK_LIMIT * BLOCK_LIMIT);
+        for (height, width) in (1..=BLOCK_LIMIT).cartesian_product(1..=BLOCK_LIMIT) {
+            let entry = catalog.entry(width, height).ok_or(eyre!("The catalog lacks the {width}x{height} room"))?;
+            let layout = Layout::parse_rendered(entry.width, entry.height, &entry.layout)
+                .wrap_err(format!("Invalid layout for the {width}x{height} room"))?;
+            blocks.push(if entry.width == width { layout } else { layout.transposed() });
+        }
+
+        Ok(Tiling { rules, blocks })
+    }
+
+    /// The tiling for desks of a shape under the rules, sweeping its blocks on first use
+    pub fn of(desk: DeskShape, rules: Rules) -> eyre::Result<Arc<Tiling>> {
+        let mut tilings = TILINGS.lock().map_err(|_| eyre!("A sweep for the tilings panicked"))?;
+        if let Some(tiling) = tilings.get(&(desk, rules)) {
+            return Ok(tiling.clone());
+        }
+
+        let _span = tracing::debug_span!("tiling", %desk, %rules).entered();
+        let catalog = catalog::sweep(BLOCK_LIMIT, desk, rules, BLOCK_BUDGET, None);
+        let tiling = Arc::new(Tiling::new(&catalog, rules)?);
+        tilings.insert((desk, rules), tiling.clone());
+        Ok(tiling)
+    }
+
+    fn block(&self, width: usize, height: usize) -> &Layout {
+        &self.blocks[(height - 1) * BLOCK_LIMIT + width - 1]
+    }
+
+    /// The desks of the blocks of a cut, 0 if the cut has no blocks
+    fn desks(&self, columns: Cut, rows: Cut) -> usize {
+        let desks = |width: usize, height: usize| {
+            if width == 0 || height == 0 { 0 } else { self.block(width, height).len() }
+        };
+
+        columns.count * rows.count * desks(columns.size, rows.size)
+            + columns.count * desks(columns.size, rows.rest)
+            + rows.count * desks(columns.rest, rows.size)
+            + desks(columns.rest, rows.rest)
+    }
+
+    /// Every cut of an axis of the given length into blocks separated by the gap
+    fn cuts(&self, length: usize) -> Vec<Cut> {
+        let gap = self.rules.spacing.gap;
+        let mut cuts = Vec::new();
+
+        for size in 1..=BLOCK_LIMIT.min(length) {
+            for rest in 0..=BLOCK_LIMIT.min(length) {
+                let covered = if rest == 0 { length + gap } else { length - rest };
+                if covered % (size + gap) == 0 {
+                    cuts.push(Cut { size, count: covered / (size + gap), rest });
+                }
+            }
+        }
+
+        cuts
+    }
+
+    /// The cuts of a room with the most desks
+    pub fn cut(&self, width: usize, height: usize) -> Option<(Cut, Cut)> {
+        let rows = self.cuts(height);
+        self.cuts(width)
+            .into_iter()
+            .cartesian_product(rows)
+            .max_by_key(|(columns, rows)| self.desks(*columns, *rows))
+    }
+
+    /// Composes the layout of a room from its best cut, checking that the desks keep the
+    /// spacing across the seams between the blocks
+    pub fn compose(&self, width: usize, height: usize) -> eyre::Result<Layout> {
+        let mut layout = Layout::new(width, height).under(self.rules);
+        let Some((columns, rows)) = self.cut(width, height) else {
+            return Ok(layout);
+        };
+
+        let gap = self.rules.spacing.gap;
+        for ((y, block_height), (x, block_width)) in rows.blocks(gap).cartesian_product(columns.blocks(gap).collect_vec()) {
+            for desk in &self.block(block_width, block_height).desks {
+                layout.place(Desk { x: x + desk.x, y: y + desk.y, ..*desk });
+            }
+        }
+
+        layout.check_spacing().wrap_err(format!("The seams of the {columns:?} by {rows:?} cut break the spacing"))?;
+        Ok(layout)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Tiling;
+    use crate::layout::Layout;
+    use crate::room::{DeskShape, Metric, Rules, Spacing};
+
+    #[test]
+    pub fn test_compose() {
+        let tiling = Tiling::of(DeskShape::new(2, 1), Rules::default()).unwrap();
+        for (width, height) in [(1, 1), (5, 5), (13, 29), (98, 62), (96, 87)] {
+            let layout = tiling.compose(width, height).unwrap();
+            assert_eq!(layout.len(), (width + 1) * (height + 1) / 6, "{width}x{height}");
+            layout.check_shape(DeskShape::new(2, 1)).unwrap();
+        }
+
+        let rules = Rules { spacing: Spacing::new(Metric::Chebyshev, 2), chairs: true };
+        let layout = Tiling::of(DeskShape::new(2, 1), rules).unwrap().compose(40, 30).unwrap();
+        layout.check_chairs().unwrap();
+        let mut greedy = Layout::new(40, 30).under(rules);
+        greedy.fill_greedy(DeskShape::new(2, 1));
+        assert!(layout.len() >= greedy.len(), "{} desks, greedy places {}", layout.len(), greedy.len());
+    }
+}
@@ -0,0 +1,173 @@
+//! Solving levels again whenever their inputs or outputs change on disk
+
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc;
+use std::time::Duration;
+
+use eyre::WrapErr;
+use notify::{EventKind, RecursiveMode, Watcher};
+
+use crate::levels::{Level, Settings, INPUT_DIR, LEVELS, OUTPUT_DIR};
+use crate::report::{LevelReport, RunReport};
+use crate::summary::Table;
+
+/// How long to wait for further file events before acting on a batch of changes
+const DEBOUNCE: Duration = Duration::from_millis(200);
+
+/// What has to be done after a batch of file changes
+#[derive(Default)]
+struct Changes {
+    /// Levels whose inputs changed and have to be solved again
+    levels: BTreeSet<usize>,
+    /// Output files that were edited and have to be verified again, per level and task
+    outputs: BTreeMap<(usize, usize), PathBuf>,
+}
+
+impl Changes {
+    fn is_empty(&self) -> bool {
+        self.levels.is_empty() && self.outputs.is_empty()
+    }
+
+    fn add(&mut self, path: &Path) {
+        for (index, level) in LEVELS.iter().enumerate() {
+            if let Some(_task) = level.task_of(path, "in") {
+                self.levels.insert(index);
+            } else if let Some(task) = level.task_of(path, "out") {
+                self.outputs.insert((index, task), path.to_owned());
+            }
+        }
+    }
+
+    /// Drops the outputs of levels that are solved again anyway and the outputs that still hold
+    /// what was `written` to them, so that our own writes do not trigger a verification
+    fn skip_written(&mut self, written: &HashMap<PathBuf, String>) {
+        let levels = &self.levels;
+        self.outputs.retain(|(level, _), path| {
+            let unchanged = std::fs::read_to_string(&*path)
+                .is_ok_and(|content| written.get(&*path) == Some(&content));
+            !levels.contains(level) && !unchanged
+        });
+    }
+}
+
+/// Keeps the solutions in sync with the files in the `inputs` and `out` directories
+///
+/// Levels are solved again whenever one of their inputs changes and output files
+/// are verified again whenever they are edited by hand. Each round prints a table
+/// with the outcome of every affected task.
+pub fn watch(settings: &Settings) -> eyre::Result<()> {
+    let (sender, receiver) = mpsc::channel();
+    let mut watcher = notify::recommended_watcher(sender).wrap_err("Cannot create file watcher")?;
+
+    std::fs::create_dir_all(OUTPUT_DIR).wrap_err(format!("Cannot create '{OUTPUT_DIR}' directory"))?;
+    for dir in [INPUT_DIR, OUTPUT_DIR] {
+        watcher.watch(Path::new(dir), RecursiveMode::Recursive)
+            .wrap_err(format!("Cannot watch '{dir}'"))?;
+    }
+
+    // Contents of the outputs written by the last runs, so our own writes do not trigger a verification
+    let mut written = HashMap::new();
+
+    let mut changes = Changes { levels: (0..LEVELS.len()).collect(), ..Changes::default() };
+    loop {
+        if !changes.is_empty() {
+            let report = apply(&changes, settings, &mut written);
+            print!("{}", Table(&report));
+            println!("Watching for changes in '{INPUT_DIR}' and '{OUTPUT_DIR}'");
+        }
+
+        let event = receiver.recv().wrap_err("File watcher stopped")?;
+        let mut events = vec![event];
+        while let Ok(event) = receiver.recv_timeout(DEBOUNCE) {
+            events.push(event);
+        }
+
+        changes = Changes::default();
+        for event in events {
+            match event {
+                Ok(event) if matches!(event.kind, EventKind::Create(_) | EventKind::Modify(_)) => {
+                    event.paths.iter().for_each(|path| changes.add(path));
+                },
+                Ok(_) => {},
+                Err(error) => tracing::warn!("File watcher error: {error}"),
+            }
+        }
+
+        changes.skip_written(&written);
+    }
+}
+
+fn apply(changes: &Changes, settings: &Settings, written: &mut HashMap<PathBuf, String>) -> RunReport {
+    let mut report = RunReport::new();
+
+    for level in changes.levels.iter().map(|index| &LEVELS[*index]) {
+        if let Err(errors) = level.run_from_disk(&mut report, settings) {
+            tracing::debug!("{} failed with {} errors", level.name, errors.len());
+        }
+        remember_outputs(level, written);
+    }
+
+    let mut verified = BTreeMap::<usize, LevelReport>::new();
+    for ((index, task), path) in &changes.outputs {
+        let level = &LEVELS[*index];
+        let (task, result) = level.verify_file(*task, path);
+        if let Err(errors) = result {
+            tracing::debug!("{} failed verification with {} errors", path.display(), errors.len());
+        }
+        if let Ok(content) = std::fs::read_to_string(path) {
+            written.insert(path.clone(), content);
+        }
+
+        verified.entry(*index)
+            .or_insert_with(|| LevelReport::new(level.number, level.name))
+            .push(task);
+    }
+    report.levels.extend(verified.into_values());
+
+    report
+}
+
+fn remember_outputs(level: &Level, written: &mut HashMap<PathBuf, String>) {
+    for task in level.tasks {
+        let path = level.output_path(task);
+        if let Ok(content) = std::fs::read_to_string(&path) {
+            written.insert(path, content);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::Changes;
+    use crate::levels;
+
+    #[test]
+    pub fn test_own_writes() {
+        let dir = std::env::temp_dir().join(format!("ccc-watch-test-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let level5 = levels::LEVELS.iter().position(|level| level.number == 5).unwrap();
+        let (first, second) = (dir.join("level5_1.out"), dir.join("level5_2.out"));
+        std::fs::write(&first, "XX..\n").unwrap();
+        std::fs::write(&second, "X.X.\n").unwrap();
+        let written = HashMap::from([(first.clone(), "XX..\n".to_string()), (second.clone(), "XX..\n".to_string())]);
+
+        let mut changes = Changes::default();
+        changes.add(&first);
+        changes.add(&second);
+        changes.add(&dir.join("notes.txt"));
+        changes.skip_written(&written);
+        assert_eq!(changes.outputs.into_iter().collect::<Vec<_>>(), [((level5, 1), second.clone())]);
+
+        let mut changes = Changes::default();
+        changes.add(&second);
+        changes.add(&dir.join("level5_3.in"));
+        assert_eq!(changes.levels.iter().copied().collect::<Vec<_>>(), [level5]);
+        changes.skip_written(&written);
+        assert!(changes.outputs.is_empty());
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}